@@ -0,0 +1,31 @@
+use tui::prelude::*;
+
+fn main() {
+    let app = App::new_inline(Prompt, 2).unwrap();
+    app.run().unwrap();
+}
+
+struct Prompt;
+
+impl Component for Prompt {
+    type Message = String;
+    type View = Stack<String>;
+
+    fn on_message(&mut self, ctx: &mut Context<String>, _msg: &String) -> Handled {
+        ctx.quit();
+        Handled::Yes
+    }
+
+    fn build(&self) -> Self::View {
+        let mut stack = Stack::new();
+
+        stack.push(
+            Label::new("What's your name?").with_style(Style::new().with_fg(Color::Cyan)),
+            SizeConstraint::fixed(1),
+        );
+        stack.push(TextField::new().on_enter(|s| s), SizeConstraint::fixed(1));
+        stack.set_focus(Some(1));
+
+        stack
+    }
+}
@@ -0,0 +1,106 @@
+//! A log that scrolls as lines are added, for measuring how much is written to
+//! the terminal per frame. Prints the average once it's done.
+//!
+//! This counts what the app writes, not what goes over the wire: measuring it
+//! over SSH is still to do.
+
+use std::time::Duration;
+
+use tui::buffer::BufferView;
+use tui::prelude::*;
+
+const LINES: usize = 300;
+
+fn main() {
+    let mut app = App::new(Log::default()).unwrap();
+    app.handle()
+        .send(Message::Start)
+        .unwrap_or_else(|_| unreachable!());
+
+    let mut bytes_written = vec![];
+    while app.run_frame().unwrap() {
+        bytes_written.push(app.stats().bytes_written);
+    }
+    drop(app);
+
+    // The first frame draws everything.
+    let frames = &bytes_written[1..];
+    println!(
+        "{} bytes per frame over {} frames",
+        frames.iter().sum::<usize>() / frames.len(),
+        frames.len()
+    );
+}
+
+#[derive(Clone)]
+enum Message {
+    Start,
+    Tick,
+}
+
+#[derive(Default)]
+struct Log {
+    lines: Vec<(Level, String)>,
+}
+
+#[derive(Clone, Copy)]
+enum Level {
+    Info,
+    Warn,
+}
+
+impl Component for Log {
+    type Message = Message;
+    type View = Container<Message>;
+
+    fn on_message(&mut self, ctx: &mut Context<Message>, msg: &Message) -> Handled {
+        match msg {
+            Message::Start => {
+                ctx.every(Duration::from_millis(5), Message::Tick);
+            }
+            Message::Tick => {
+                let n = self.lines.len();
+                let level = if n % 7 == 3 { Level::Warn } else { Level::Info };
+                let text = format!("request {n} handled in {}ms", n * 37 % 300);
+                self.lines.push((level, text));
+
+                if self.lines.len() == LINES {
+                    ctx.quit();
+                }
+                ctx.rebuild_view();
+            }
+        }
+        Handled::Yes
+    }
+
+    fn build(&self) -> Self::View {
+        // Only the lines that could fit on the screen.
+        let start = self.lines.len().saturating_sub(200);
+        let view = LogView {
+            lines: self.lines[start..].to_vec(),
+        };
+
+        Container::new(view).with_border(LineStyle::Line, Style::new().with_fg(Color::Blue))
+    }
+}
+
+struct LogView {
+    lines: Vec<(Level, String)>,
+}
+
+impl View<Message> for LogView {
+    fn render(&self, buf: &mut BufferView) {
+        let height = buf.size().y as usize;
+        let start = self.lines.len().saturating_sub(height);
+
+        for (y, (level, text)) in self.lines[start..].iter().enumerate() {
+            let (label, color) = match level {
+                Level::Info => ("INFO ", Color::Green),
+                Level::Warn => ("WARN ", Color::Yellow),
+            };
+
+            let x = buf.put_str([0, y as u16], label, Style::new().with_fg(color));
+            buf.put_str([x, y as u16], text, Style::default());
+        }
+    }
+}
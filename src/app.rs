@@ -1,70 +1,174 @@
 use std::io;
+use std::sync::mpsc::{self, Receiver, SendError, Sender};
 use std::time::{Duration, Instant};
 
+#[cfg(feature = "async")]
+use futures::future;
+
 use super::component::{Component, View};
-use crate::buffer::Buffer;
-use crate::draw_buffer::draw_diff;
-use crate::platform::event::Events;
+#[cfg(feature = "async")]
+use crate::platform::event::AsyncEvents;
+use crate::platform::event::{Event, EventWaker, Events, KeyCode, KeyEvent, Modifiers};
 use crate::platform::linux::LinuxTerminal;
-use crate::platform::{Terminal, Writer};
-
-pub struct App<C: Component> {
+use crate::platform::{Terminal, Viewport, Writer};
+use crate::renderer::Renderer;
+use crate::stats::{FrameStats, StatsOverlay};
+use crate::task::{Spawn, TaskPool};
+use crate::timer::{TimerHandle, Timers};
+use crate::vec2::Vec2;
+
+pub struct App<C: Component, T: Terminal = LinuxTerminal> {
     root: C,
     root_view: C::View,
 
-    buf_old: Buffer,
-    buf_new: Buffer,
+    renderer: Renderer,
 
-    term: LinuxTerminal,
+    term: T,
+    size: Vec2,
 
     context: Context<C::Message>,
     messages_current: Vec<C::Message>, // A buffer for messages currently being processed.
 
     refresh_rate: Duration,
+    last_render: Option<Instant>,
+    suspend_key: Option<KeyEvent>,
+
+    stats: FrameStats,
+    stats_current: FrameStats, // The stats for the frame in progress.
+    stats_key: Option<KeyEvent>,
+    show_stats: bool,
+
+    // For messages sent from outside the app.
+    handle_recv: Receiver<C::Message>,
 }
 
 impl<C: Component> App<C> {
     pub fn new(root: C) -> io::Result<Self> {
+        Self::new_with_terminal(root, LinuxTerminal::init()?)
+    }
+
+    /// Create an app that draws to the given number of lines below the cursor,
+    /// rather than taking over the whole screen.
+    pub fn new_inline(root: C, height: u16) -> io::Result<Self> {
+        Self::new_with_terminal(
+            root,
+            LinuxTerminal::with_viewport(Viewport::Inline(height))?,
+        )
+    }
+}
+
+impl<C: Component, T: Terminal> App<C, T> {
+    /// Create an app that runs on the given terminal, such as a
+    /// [`TestTerminal`](crate::platform::headless::TestTerminal).
+    pub fn new_with_terminal(root: C, mut term: T) -> io::Result<Self> {
+        let size = term.size()?;
+        let (handle_send, handle_recv) = mpsc::channel();
+        let handle = AppHandle {
+            send: handle_send,
+            waker: term.events().waker(),
+        };
+
         Ok(Self {
             root_view: root.build(),
             root,
 
-            buf_old: Buffer::default(),
-            buf_new: Buffer::default(),
+            renderer: Renderer::new(),
 
-            term: LinuxTerminal::init()?,
+            term,
+            size,
 
             context: Context {
                 messages: vec![],
                 should_rebuild_view: false,
                 should_quit: false,
+                should_suspend: false,
+                should_render: true,
+
+                handle,
+                tasks: TaskPool::default(),
+                timers: Timers::default(),
             },
             messages_current: vec![],
 
             refresh_rate: Duration::from_millis(16),
+            last_render: None,
+            suspend_key: Some(KeyEvent {
+                key_code: KeyCode::Char('Z'),
+                modifiers: Modifiers::CTRL,
+            }),
+
+            stats: FrameStats::default(),
+            stats_current: FrameStats::default(),
+            stats_key: None,
+            show_stats: false,
+
+            handle_recv,
         })
     }
 
+    /// Set the shortest time between frames, so that bursts of events are drawn
+    /// together. Defaults to 16ms.
     pub fn with_refresh_rate(mut self, refresh_rate: Duration) -> Self {
         self.refresh_rate = refresh_rate;
         self
     }
 
+    /// Set the key that suspends the app, or `None` to pass it through to the
+    /// view like any other key. Defaults to Ctrl+Z.
+    pub fn with_suspend_key(mut self, suspend_key: Option<KeyEvent>) -> Self {
+        self.suspend_key = suspend_key;
+        self
+    }
+
+    /// Set a key that toggles an overlay showing [`App::stats`]. There isn't
+    /// one by default.
+    pub fn with_stats_key(mut self, stats_key: Option<KeyEvent>) -> Self {
+        self.stats_key = stats_key;
+        self
+    }
+
+    /// Timings and counts for the last frame that was rendered.
+    pub fn stats(&self) -> &FrameStats {
+        &self.stats
+    }
+
+    /// A handle for sending messages to the app from other threads.
+    pub fn handle(&self) -> AppHandle<C::Message> {
+        self.context.handle.clone()
+    }
+
+    pub fn terminal(&self) -> &T {
+        &self.term
+    }
+
+    pub fn terminal_mut(&mut self) -> &mut T {
+        &mut self.term
+    }
+
     pub fn run(mut self) -> io::Result<()> {
-        loop {
-            self.frame()?;
+        while self.run_frame()? {}
+        Ok(())
+    }
 
-            if self.context.should_quit {
-                break;
-            }
+    /// Handle events and messages for one frame, then render if anything
+    /// happened.
+    ///
+    /// This blocks until there's something to do, so an idle app doesn't use
+    /// any CPU. Returns `false` once the app has quit, in which case nothing is
+    /// rendered.
+    pub fn run_frame(&mut self) -> io::Result<bool> {
+        self.frame()?;
+        self.finish_frame()
+    }
 
-            if self.context.should_rebuild_view {
-                self.rebuild_view();
-                self.context.should_rebuild_view = false;
-            }
+    /// Hand the terminal back to the shell until the process is resumed, like
+    /// pressing Ctrl+Z in a shell.
+    pub fn suspend(&mut self) -> io::Result<()> {
+        self.term.suspend()?;
 
-            self.render()?;
-        }
+        self.size = self.term.size()?;
+        self.renderer.redraw_all();
+        self.context.should_render = true;
 
         Ok(())
     }
@@ -74,48 +178,221 @@ impl<C: Component> App<C> {
     }
 
     fn frame(&mut self) -> io::Result<()> {
-        // Work out how long we have.
-        let time = Instant::now();
-        let deadline = time
-            .checked_add(self.refresh_rate)
-            .expect("deadline overflowed");
+        self.update();
+
+        // Handle events.
+        loop {
+            let deadline = self.frame_deadline();
+            match self.term.events().read_with_deadline(deadline)? {
+                Some(event) => self.handle_event(event),
+                None => break,
+            }
+        }
+
+        self.handle_messages();
 
-        // Update the component tree.
+        Ok(())
+    }
+
+    fn update(&mut self) {
+        let start = Instant::now();
         self.root.update();
+        self.stats_current.update += start.elapsed();
+    }
 
-        // Handle events.
-        let events = self.term.events();
-        while let Some(event) = events.read_with_deadline(deadline)? {
-            let _ = self.root_view.on_event(&mut self.context, &event);
+    /// When to stop waiting for events and get on with the frame.
+    ///
+    /// If there's something to draw, events are collected until the next frame
+    /// is due. Otherwise there's no hurry, so we wait for the next timer, or
+    /// for as long as it takes.
+    fn frame_deadline(&self) -> Option<Instant> {
+        let timer = self.context.timers.next_deadline();
+
+        if !self.context.should_render && self.context.messages.is_empty() {
+            return timer;
+        }
+
+        let next_frame = self
+            .last_render
+            .map_or_else(Instant::now, |last_render| last_render + self.refresh_rate);
+        Some(timer.map_or(next_frame, |timer| timer.min(next_frame)))
+    }
+
+    fn handle_event(&mut self, event: Event) {
+        let start = Instant::now();
+        self.dispatch_event(event);
+        self.stats_current.events += start.elapsed();
+    }
+
+    fn dispatch_event(&mut self, event: Event) {
+        self.context.should_render = true;
+
+        if let Event::Resize(size) = event {
+            self.size = size;
+            self.renderer.redraw_all();
+        }
+
+        if let (Some(suspend_key), Event::Key(key_event)) = (self.suspend_key, &event) {
+            if *key_event == suspend_key {
+                self.context.suspend();
+                return;
+            }
         }
 
-        // Handle messages.
+        if let (Some(stats_key), Event::Key(key_event)) = (self.stats_key, &event) {
+            if *key_event == stats_key {
+                self.show_stats = !self.show_stats;
+                return;
+            }
+        }
+
+        let _ = self.root_view.on_event(&mut self.context, &event);
+    }
+
+    fn handle_messages(&mut self) {
+        let start = Instant::now();
+
+        self.context.messages.extend(self.handle_recv.try_iter());
+        self.context
+            .timers
+            .fire(Instant::now(), &mut self.context.messages);
+
         std::mem::swap(&mut self.messages_current, &mut self.context.messages);
+        self.context.should_render |= !self.messages_current.is_empty();
         for message in self.messages_current.drain(..) {
             let _ = self.root.on_message(&mut self.context, &message);
         }
 
-        Ok(())
+        self.stats_current.messages += start.elapsed();
+    }
+
+    /// Act on what the frame asked for, then render. Returns `false` if the app
+    /// has quit.
+    fn finish_frame(&mut self) -> io::Result<bool> {
+        if self.context.should_quit {
+            return Ok(false);
+        }
+
+        if self.context.should_suspend {
+            self.context.should_suspend = false;
+            self.suspend()?;
+        }
+
+        if self.context.should_rebuild_view {
+            self.rebuild_view();
+            self.context.should_rebuild_view = false;
+        }
+
+        if self.context.should_render {
+            self.render()?;
+        }
+
+        Ok(true)
     }
 
     fn render(&mut self) -> io::Result<()> {
-        // Resize buffer.
-        let term_size = self.term.size()?;
-        self.buf_new.resize_and_clear(term_size);
+        let start = Instant::now();
 
         // Render component to buffer.
-        let mut buf_view = self.buf_new.view(true);
+        let mut buf_view = self.renderer.start_frame(self.size);
         self.root_view.render(&mut buf_view);
+        if self.show_stats {
+            View::<C::Message>::render(&StatsOverlay::new(self.stats), &mut buf_view);
+        }
+
+        let rendered = Instant::now();
+
+        // Draw changes to terminal, or everything if the old contents of the terminal
+        // can't be trusted.
+        let cells_changed = self.renderer.draw(self.term.writer());
+
+        let drawn = Instant::now();
+        let bytes_written = self.term.writer().buffered_len();
+        self.term.writer().flush()?;
+
+        self.context.should_render = false;
+        self.last_render = Some(Instant::now());
 
-        // Draw changes to terminal.
-        // TODO: make immutable view type.
-        let buf_old_view = self.buf_old.view(false);
-        draw_diff(&buf_old_view, &buf_view, self.term.writer());
+        self.stats = FrameStats {
+            render: rendered - start,
+            draw_diff: drawn - rendered,
+            flush: drawn.elapsed(),
+            bytes_written,
+            cells_changed,
+            ..std::mem::take(&mut self.stats_current)
+        };
 
-        // Swap buffers.
-        self.buf_old.clone_from(&self.buf_new);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async")]
+impl<C: Component, T: Terminal> App<C, T>
+where
+    T::Events: AsyncEvents,
+{
+    /// Like [`App::run`], but waits for events without blocking the thread, so
+    /// that it can run alongside other futures.
+    pub async fn run_async(mut self) -> io::Result<()> {
+        while self.run_frame_async().await? {}
+        Ok(())
+    }
+
+    /// Like [`App::run_frame`], but waits for events without blocking the
+    /// thread.
+    pub async fn run_frame_async(&mut self) -> io::Result<bool> {
+        self.update();
+
+        loop {
+            let deadline = self.frame_deadline();
+            let events = self.term.events();
+            let event = future::poll_fn(|cx| events.poll_event(cx, deadline)).await?;
+
+            match event {
+                Some(event) => self.handle_event(event),
+                None => break,
+            }
+        }
+
+        self.handle_messages();
+        self.finish_frame()
+    }
+}
+
+/// Sends messages to an [`App`] from outside of it, e.g. from a background
+/// thread.
+///
+/// The app handles them as soon as it can, rather than waiting for the next
+/// frame.
+pub struct AppHandle<Message> {
+    send: Sender<Message>,
+    waker: EventWaker,
+}
+
+impl<Message> Clone for AppHandle<Message> {
+    fn clone(&self) -> Self {
+        Self {
+            send: self.send.clone(),
+            waker: self.waker.clone(),
+        }
+    }
+}
+
+impl<Message> AppHandle<Message> {
+    /// Send a message, failing if the app has been dropped.
+    pub fn send(&self, message: Message) -> Result<(), SendError<Message>> {
+        self.queue(message)?;
+        self.waker.wake();
+        Ok(())
+    }
+
+    /// Send a message without waking the app, for when something else will.
+    pub(crate) fn queue(&self, message: Message) -> Result<(), SendError<Message>> {
+        self.send.send(message)
+    }
 
-        self.term.writer().flush()
+    pub(crate) fn waker(&self) -> &EventWaker {
+        &self.waker
     }
 }
 
@@ -123,6 +400,12 @@ pub struct Context<Message> {
     messages: Vec<Message>,
     should_rebuild_view: bool,
     should_quit: bool,
+    should_suspend: bool,
+    should_render: bool,
+
+    handle: AppHandle<Message>,
+    tasks: TaskPool,
+    timers: Timers<Message>,
 }
 
 impl<Message> Context<Message> {
@@ -137,4 +420,184 @@ impl<Message> Context<Message> {
     pub fn rebuild_view(&mut self) {
         self.should_rebuild_view = true;
     }
+
+    /// Suspend the app once the current frame's messages have been handled.
+    pub fn suspend(&mut self) {
+        self.should_suspend = true;
+    }
+
+    /// Run a closure on a background thread, without blocking rendering.
+    ///
+    /// ```ignore
+    /// ctx.spawn(move || expensive()).then(Message::Done);
+    /// ```
+    pub fn spawn<F, T>(&mut self, work: F) -> Spawn<'_, Message, F>
+    where
+        F: FnOnce() -> T + Send + 'static,
+    {
+        Spawn {
+            pool: &mut self.tasks,
+            handle: &self.handle,
+            work,
+        }
+    }
+
+    /// Send a message once the given time has passed.
+    pub fn after(&mut self, delay: Duration, message: Message) -> TimerHandle {
+        self.timers.after(Instant::now(), delay, message)
+    }
+
+    /// Send a message every time the given interval passes, until cancelled.
+    ///
+    /// Panics if the interval is zero.
+    pub fn every(&mut self, interval: Duration, message: Message) -> TimerHandle
+    where
+        Message: Clone + 'static,
+    {
+        self.timers.every(Instant::now(), interval, message)
+    }
+
+    /// The number of spawned tasks that haven't finished yet, e.g. for showing
+    /// a spinner.
+    pub fn tasks_in_flight(&self) -> usize {
+        self.tasks.in_flight()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    use crate::buffer::BufferView;
+    use crate::platform::headless::TestTerminal;
+    use crate::platform::Terminal;
+    use crate::prelude::*;
+
+    /// Keeps the keys that reach the view.
+    struct KeyLog(Rc<RefCell<Vec<KeyEvent>>>);
+
+    impl Component for KeyLog {
+        type Message = ();
+        type View = KeyLogView;
+
+        fn build(&self) -> Self::View {
+            KeyLogView(self.0.clone())
+        }
+    }
+
+    struct KeyLogView(Rc<RefCell<Vec<KeyEvent>>>);
+
+    impl View<()> for KeyLogView {
+        fn on_event(&mut self, _ctx: &mut Context<()>, event: &Event) -> Handled {
+            match event {
+                Event::Key(key_event) => {
+                    self.0.borrow_mut().push(*key_event);
+                    Handled::Yes
+                }
+                _ => Handled::No,
+            }
+        }
+
+        fn render(&self, _buf: &mut BufferView) {}
+    }
+
+    #[test]
+    fn suspend() {
+        let keys = Rc::new(RefCell::new(vec![]));
+        let mut app =
+            App::new_with_terminal(KeyLog(keys.clone()), TestTerminal::new([4, 1])).unwrap();
+        let ctrl_z = KeyEvent {
+            key_code: KeyCode::Char('Z'),
+            modifiers: Modifiers::CTRL,
+        };
+
+        app.terminal_mut().events().push_key(ctrl_z);
+        assert!(app.run_frame().unwrap());
+        assert_eq!(app.terminal().times_suspended(), 1);
+        assert!(keys.borrow().is_empty());
+
+        // Once unbound, the key goes to the view instead.
+        let mut app = app.with_suspend_key(None);
+        app.terminal_mut().events().push_key(ctrl_z);
+        assert!(app.run_frame().unwrap());
+        assert_eq!(app.terminal().times_suspended(), 1);
+        assert_eq!(*keys.borrow(), [ctrl_z]);
+    }
+
+    /// Keeps the messages it's sent, and shows the last one.
+    struct Inbox(Rc<RefCell<Vec<String>>>);
+
+    impl Component for Inbox {
+        type Message = String;
+        type View = Label;
+
+        fn on_message(&mut self, ctx: &mut Context<String>, msg: &String) -> Handled {
+            self.0.borrow_mut().push(msg.clone());
+            ctx.rebuild_view();
+            Handled::Yes
+        }
+
+        fn build(&self) -> Self::View {
+            Label::new(self.0.borrow().last().cloned().unwrap_or_default())
+        }
+    }
+
+    #[test]
+    fn handle() {
+        let inbox = Rc::new(RefCell::new(vec![]));
+        let mut app =
+            App::new_with_terminal(Inbox(inbox.clone()), TestTerminal::new([8, 1])).unwrap();
+
+        let handle = app.handle();
+        let sent = std::thread::spawn(move || handle.send("remote".to_owned()).is_ok());
+        assert!(sent.join().unwrap());
+
+        assert!(app.run_frame().unwrap());
+        assert_eq!(*inbox.borrow(), ["remote"]);
+        assert_eq!(app.terminal_mut().writer().line(0), "remote");
+
+        let handle = app.handle();
+        drop(app);
+        assert!(handle.send("late".to_owned()).is_err());
+    }
+
+    #[test]
+    fn idle() {
+        struct Counter(Rc<Cell<usize>>);
+
+        impl View<()> for Counter {
+            fn render(&self, _buf: &mut BufferView) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        struct Idle(Rc<Cell<usize>>);
+
+        impl Component for Idle {
+            type Message = ();
+            type View = Counter;
+
+            fn build(&self) -> Self::View {
+                Counter(self.0.clone())
+            }
+        }
+
+        let renders = Rc::new(Cell::new(0));
+        let mut app =
+            App::new_with_terminal(Idle(renders.clone()), TestTerminal::new([4, 1])).unwrap();
+
+        assert!(app.run_frame().unwrap());
+        assert!(app.run_frame().unwrap());
+        assert_eq!(renders.get(), 1);
+
+        app.terminal_mut().events().push_str("a");
+        assert!(app.run_frame().unwrap());
+        assert_eq!(renders.get(), 2);
+
+        app.handle().send(()).unwrap_or_else(|_| panic!());
+        assert!(app.run_frame().unwrap());
+        assert!(app.run_frame().unwrap());
+        assert_eq!(renders.get(), 3);
+    }
 }
@@ -1,27 +1,132 @@
+use std::fmt;
 use std::ops::{Index, IndexMut};
 
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
 use crate::style::Style;
 use crate::vec2::Vec2;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The longest grapheme cluster a cell can hold, in bytes. Longer ones are
+/// replaced by U+FFFD, padded to the same width.
+const SYMBOL_CAP: usize = 31;
+
+/// A grapheme cluster stored inline, so that cells stay `Copy`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Symbol {
+    bytes: [u8; SYMBOL_CAP],
+    len: u8,
+}
+
+impl Symbol {
+    fn new(s: &str) -> Self {
+        let s = if s.len() > SYMBOL_CAP { "\u{fffd}" } else { s };
+
+        let mut bytes = [0; SYMBOL_CAP];
+        bytes[..s.len()].copy_from_slice(s.as_bytes());
+
+        Self {
+            bytes,
+            len: s.len() as u8,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        // Safety: the bytes were copied from a `str`.
+        unsafe { std::str::from_utf8_unchecked(&self.bytes[..self.len as usize]) }
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// A single cell of the terminal, holding one grapheme cluster.
+///
+/// Wide (two column) clusters are followed by a continuation cell, which holds
+/// nothing and has a width of zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Cell {
-    pub c: char,
+    symbol: Symbol,
+    width: u8,
     pub style: Style,
 }
 
 impl Default for Cell {
     fn default() -> Self {
-        Self {
-            c: ' ',
-            style: Style::default(),
-        }
+        Self::new(' ', Style::default())
     }
 }
 
 impl Cell {
     pub fn new(c: char, style: Style) -> Self {
-        Self { c, style }
+        Self::from_grapheme(c.encode_utf8(&mut [0; 4]), style)
+    }
+
+    /// Create a cell from a single grapheme cluster.
+    ///
+    /// Control characters can't be displayed, so they're replaced by a space.
+    pub fn from_grapheme(grapheme: &str, style: Style) -> Self {
+        let width = grapheme_width(grapheme);
+        if width == 0 {
+            return Self::new(' ', style);
+        }
+
+        // Keep the width of the original so the rest of the row stays put.
+        let symbol = match (grapheme.len() > SYMBOL_CAP, width) {
+            (false, _) => Symbol::new(grapheme),
+            (true, 1) => Symbol::new("\u{fffd}"),
+            (true, _) => Symbol::new("\u{fffd} "),
+        };
+
+        Self {
+            symbol,
+            width: width as u8,
+            style,
+        }
+    }
+
+    /// Create a cell for the second column of a wide cell.
+    pub fn continuation(style: Style) -> Self {
+        Self {
+            symbol: Symbol::new(""),
+            width: 0,
+            style,
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        self.symbol.as_str()
+    }
+
+    /// The number of columns the cell takes up.
+    pub fn width(&self) -> u16 {
+        self.width as u16
     }
+
+    pub fn is_continuation(&self) -> bool {
+        self.width == 0
+    }
+}
+
+/// The number of columns a grapheme cluster takes up: 1 or 2, or 0 for control
+/// characters.
+pub fn grapheme_width(grapheme: &str) -> u16 {
+    match grapheme.chars().next() {
+        None => 0,
+        Some(c) if c.is_control() => 0,
+        Some(_) => grapheme.width().clamp(1, 2) as u16,
+    }
+}
+
+/// The number of columns a string takes up when drawn with
+/// [`BufferView::put_str`].
+pub fn str_width(s: &str) -> usize {
+    s.graphemes(true)
+        .map(|grapheme| grapheme_width(grapheme) as usize)
+        .sum()
 }
 
 #[derive(Default, Debug)]
@@ -62,7 +167,7 @@ impl Buffer {
 
         if size != self.size {
             self.buf.clear();
-            self.buf.extend(std::iter::repeat(None).take(size.area()));
+            self.buf.extend(std::iter::repeat_n(None, size.area()));
             self.size = size;
         } else {
             self.buf.fill(None);
@@ -71,7 +176,94 @@ impl Buffer {
         self.cursor = None;
     }
 
-    pub fn view(&mut self, set_cursor: bool) -> BufferView {
+    pub fn size(&self) -> Vec2 {
+        self.size
+    }
+
+    pub fn get(&self, index: impl Into<Vec2>) -> Option<&Option<Cell>> {
+        let index: Vec2 = index.into();
+
+        if index.x >= self.size.x || index.y >= self.size.y {
+            return None;
+        }
+
+        self.buf
+            .get(index.y as usize * self.size.x as usize + index.x as usize)
+    }
+
+    pub fn cursor(&self) -> Option<Vec2> {
+        self.cursor
+    }
+
+    /// Render the buffer as a plain-text grid, for snapshot tests.
+    ///
+    /// The grid is framed so that trailing spaces survive editors that strip
+    /// them. Empty cells are shown as spaces.
+    pub fn to_text_snapshot(&self) -> String {
+        self.snapshot_grid(|cell| match cell {
+            Some(cell) => cell.symbol().to_owned(),
+            None => " ".to_owned(),
+        })
+    }
+
+    /// Render the buffer as a plain-text grid followed by a style annotation
+    /// layer.
+    ///
+    /// The annotation layer is a second grid where each cell is replaced by a
+    /// letter identifying its style, with `.` for the default style, followed
+    /// by a legend mapping the letters to styles.
+    pub fn to_styled_snapshot(&self) -> String {
+        const KEYS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+        let mut styles: Vec<Style> = vec![];
+        let annotations = self.snapshot_grid(|cell| {
+            let style = cell.map_or(Style::default(), |cell| cell.style);
+            if style == Style::default() {
+                return ".".to_owned();
+            }
+
+            let idx = match styles.iter().position(|&s| s == style) {
+                Some(idx) => idx,
+                None => {
+                    styles.push(style);
+                    styles.len() - 1
+                }
+            };
+
+            KEYS.chars().nth(idx).unwrap_or('?').to_string()
+        });
+
+        let mut snapshot = self.to_text_snapshot();
+        snapshot.push_str(&annotations);
+        for (key, style) in KEYS.chars().zip(&styles) {
+            snapshot.push_str(&format!("{key}: {style:?}\n"));
+        }
+
+        snapshot
+    }
+
+    /// Build a framed grid from the text `f` gives for each cell.
+    ///
+    /// Continuation cells give an empty string in the text layer, so that wide
+    /// cells line up in a monospace font.
+    fn snapshot_grid(&self, mut f: impl FnMut(Option<&Cell>) -> String) -> String {
+        let border = format!("+{}+\n", "-".repeat(self.size.x as usize));
+
+        let mut grid = border.clone();
+        for y in 0..self.size.y {
+            grid.push('|');
+            for x in 0..self.size.x {
+                let cell = self.get([x, y]).and_then(Option::as_ref);
+                grid.push_str(&f(cell));
+            }
+            grid.push_str("|\n");
+        }
+        grid.push_str(&border);
+
+        grid
+    }
+
+    pub fn view(&mut self, set_cursor: bool) -> BufferView<'_> {
         let start = [0, 0].into();
         let end = self.size;
 
@@ -102,7 +294,7 @@ impl<'a> BufferView<'a> {
         start: impl Into<Vec2>,
         end: impl Into<Vec2>,
         set_cursor: bool,
-    ) -> BufferView {
+    ) -> BufferView<'_> {
         let start = start.into();
         let end = end.into();
 
@@ -127,6 +319,14 @@ impl<'a> BufferView<'a> {
         self.buf.buf.get_mut(index)
     }
 
+    /// The cells of a row of the view, or nothing if it's out of bounds.
+    pub(crate) fn row(&self, y: u16) -> &[Option<Cell>] {
+        match self.index([0, y]) {
+            Some(start) => &self.buf.buf[start..start + self.size().x as usize],
+            None => &[],
+        }
+    }
+
     fn index(&self, index: impl Into<Vec2>) -> Option<usize> {
         let index: Vec2 = self.start + index.into();
 
@@ -155,6 +355,40 @@ impl<'a> BufferView<'a> {
         })
     }
 
+    /// Write a string starting at `pos`, stopping at the edge of the view.
+    ///
+    /// The string is split into grapheme clusters, with wide ones taking up two
+    /// cells. Control characters are skipped. Returns the number of columns
+    /// written to.
+    pub fn put_str(&mut self, pos: impl Into<Vec2>, s: &str, style: Style) -> u16 {
+        let pos: Vec2 = pos.into();
+        let mut x = pos.x;
+
+        for grapheme in s.graphemes(true) {
+            let width = grapheme_width(grapheme);
+            if width == 0 {
+                continue;
+            }
+
+            if x as usize + width as usize > self.size().x as usize {
+                break;
+            }
+
+            if let Some(cell) = self.get_mut([x, pos.y]) {
+                *cell = Some(Cell::from_grapheme(grapheme, style));
+            }
+            if width == 2 {
+                if let Some(cell) = self.get_mut([x + 1, pos.y]) {
+                    *cell = Some(Cell::continuation(style));
+                }
+            }
+
+            x += width;
+        }
+
+        x - pos.x
+    }
+
     pub fn cursor(&self) -> Option<Vec2> {
         self.buf.cursor.and_then(|index| {
             if index.both_gteq(self.start) && index.both_lt(self.end) {
@@ -182,8 +416,8 @@ impl<I: Into<Vec2>> IndexMut<I> for BufferView<'_> {
 
 #[cfg(test)]
 mod tests {
-    use super::{Buffer, Cell};
-    use crate::style::Style;
+    use super::{str_width, Buffer, Cell};
+    use crate::style::{Style, Underline};
 
     macro_rules! assert_matches {
         ($e:expr, $p:pat $( if $guard:expr )?) => {{
@@ -218,14 +452,77 @@ mod tests {
         view.set_cursor(Some([10, 10]));
         assert_eq!(view.cursor(), None);
 
-        assert_matches!(view[[0, 0]], Some(cell) if cell.c == 'a');
-        assert_matches!(view[[0, 9]], Some(cell) if cell.c == 'b');
-        assert_matches!(view[[1, 0]], Some(cell) if cell.c == 'c');
-        assert_matches!(view[[9, 9]], Some(cell) if cell.c == 'd');
+        assert_matches!(view[[0, 0]], Some(cell) if cell.symbol() == "a");
+        assert_matches!(view[[0, 9]], Some(cell) if cell.symbol() == "b");
+        assert_matches!(view[[1, 0]], Some(cell) if cell.symbol() == "c");
+        assert_matches!(view[[9, 9]], Some(cell) if cell.symbol() == "d");
 
         assert!(view.get([10, 10]).is_none());
     }
 
+    #[test]
+    fn snapshot() {
+        let mut buffer = Buffer::new([4, 2]);
+        let mut view = buffer.view(true);
+
+        let style = Style::new().with_underline(Underline::Single);
+        view[[0, 0]] = Some(Cell::new('a', Style::default()));
+        view[[1, 0]] = Some(Cell::new('b', style));
+        view[[3, 1]] = Some(Cell::new('c', style));
+
+        assert_eq!(
+            buffer.to_text_snapshot(),
+            "+----+\n|ab  |\n|   c|\n+----+\n"
+        );
+        assert_eq!(
+            buffer.to_styled_snapshot(),
+            format!(
+                "+----+\n|ab  |\n|   c|\n+----+\n+----+\n|.a..|\n|...a|\n+----+\na: {style:?}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn put_str() {
+        let mut buffer = Buffer::new([4, 1]);
+        let mut view = buffer.view(true);
+
+        // "e" with a combining acute accent, a tab, which is skipped, then two wide
+        // characters, the second of which doesn't fit.
+        assert_eq!(view.put_str([0, 0], "e\u{301}\t日本", Style::default()), 3);
+
+        assert_matches!(view[[0, 0]], Some(cell) if cell.symbol() == "e\u{301}" && cell.width() == 1);
+        assert_matches!(view[[1, 0]], Some(cell) if cell.symbol() == "日" && cell.width() == 2);
+        assert_matches!(view[[2, 0]], Some(cell) if cell.is_continuation());
+        assert_matches!(view[[3, 0]], None);
+
+        assert_eq!(buffer.to_text_snapshot(), "+----+\n|e\u{301}日 |\n+----+\n");
+        assert_eq!(str_width("e\u{301}\t日本"), 5);
+    }
+
+    #[test]
+    fn long_grapheme() {
+        let mut buffer = Buffer::new([5, 1]);
+        let mut view = buffer.view(true);
+
+        // A single wide grapheme that's too long to store.
+        let kiss =
+            "\u{1f469}\u{1f3fd}\u{200d}\u{2764}\u{fe0f}\u{200d}\u{1f48b}\u{200d}\u{1f468}\u{1f3ff}";
+        assert_eq!(
+            view.put_str([0, 0], &format!("a{kiss}b"), Style::default()),
+            4
+        );
+
+        assert_matches!(view[[1, 0]], Some(cell) if cell.symbol() == "\u{fffd} " && cell.width() == 2);
+        assert_matches!(view[[2, 0]], Some(cell) if cell.is_continuation());
+        assert_matches!(view[[3, 0]], Some(cell) if cell.symbol() == "b");
+
+        assert_eq!(
+            buffer.to_text_snapshot(),
+            "+-----+\n|a\u{fffd} b |\n+-----+\n"
+        );
+    }
+
     #[test]
     fn view() {
         let mut buffer = Buffer::new([10, 10]);
@@ -245,17 +542,17 @@ mod tests {
         view2.set_cursor(Some([7, 7]));
         assert_eq!(view2.cursor(), Some([7, 7].into()));
 
-        assert_matches!(view2[[0, 0]], Some(cell) if cell.c == 'a');
-        assert_matches!(view2[[0, 7]], Some(cell) if cell.c == 'b');
-        assert_matches!(view2[[1, 0]], Some(cell) if cell.c == 'c');
-        assert_matches!(view2[[7, 7]], Some(cell) if cell.c == 'd');
+        assert_matches!(view2[[0, 0]], Some(cell) if cell.symbol() == "a");
+        assert_matches!(view2[[0, 7]], Some(cell) if cell.symbol() == "b");
+        assert_matches!(view2[[1, 0]], Some(cell) if cell.symbol() == "c");
+        assert_matches!(view2[[7, 7]], Some(cell) if cell.symbol() == "d");
 
         assert!(view2.get([8, 8]).is_none());
 
-        assert_matches!(view[[1, 1]], Some(cell) if cell.c == 'a');
-        assert_matches!(view[[1, 8]], Some(cell) if cell.c == 'b');
-        assert_matches!(view[[2, 1]], Some(cell) if cell.c == 'c');
-        assert_matches!(view[[8, 8]], Some(cell) if cell.c == 'd');
+        assert_matches!(view[[1, 1]], Some(cell) if cell.symbol() == "a");
+        assert_matches!(view[[1, 8]], Some(cell) if cell.symbol() == "b");
+        assert_matches!(view[[2, 1]], Some(cell) if cell.symbol() == "c");
+        assert_matches!(view[[8, 8]], Some(cell) if cell.symbol() == "d");
 
         assert_eq!(view.cursor(), Some([8, 8].into()));
     }
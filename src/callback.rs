@@ -1,3 +1,4 @@
+#[allow(unused_macros)]
 macro_rules! callback_type {
     ($(#[$m:meta])* $name:ident $(<$($ty_param:ident),+>)? ($($arg:ident : $arg_ty:ty),*) -> $ret_ty:ty ) => {
         #[allow(clippy::unused_unit)]
@@ -38,6 +38,10 @@ pub trait Component: Sized {
         Handled::No
     }
 
+    /// Called at the start of every frame.
+    ///
+    /// Frames only happen when something wakes the app, like an event or a
+    /// message, so use [`Context::every`] for things that change over time.
     fn update(&mut self) {}
 
     fn build(&self) -> Self::View;
@@ -1,12 +1,33 @@
-use crate::buffer::BufferView;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+
+use crate::buffer::{BufferView, Cell};
 use crate::platform::Writer;
-use crate::style::Style;
+use crate::style::{Style, Underline};
 use crate::vec2::Vec2;
 
-pub fn draw_diff(old: &BufferView, new: &BufferView, w: &mut impl Writer) {
+/// A blank run to the end of the line must have more changed cells than this
+/// to be cleared with `CSI K` rather than by writing spaces.
+const CLEAR_LINE_MIN: usize = 3;
+
+/// The same for other blank runs and `CSI n X`, which costs more as it doesn't
+/// move the cursor past the run.
+const ERASE_CHARS_MIN: usize = 12;
+
+/// A run of the same character must have more changed cells than this to be
+/// drawn with [`Writer::repeat_str`], which can use `CSI n b`.
+const REPEAT_MIN: usize = 6;
+
+/// A scroll must save drawing at least this many non-blank rows to be worth the
+/// escape sequences.
+const SCROLL_MIN: usize = 2;
+
+type Row = Vec<Option<Cell>>;
+
+/// Draw the changes from `old` to `new`, returning how many cells were drawn.
+pub fn draw_diff(old: &BufferView, new: &BufferView, w: &mut impl Writer) -> usize {
     if old.size() != new.size() {
-        draw_no_diff(new, w);
-        return;
+        return draw_no_diff(new, w);
     }
 
     w.set_cursor_home();
@@ -17,29 +38,78 @@ pub fn draw_diff(old: &BufferView, new: &BufferView, w: &mut impl Writer) {
 
     w.write_style(style);
 
+    let mut cells_drawn = 0;
+
+    // Shift the old contents into place, so that only what's new is drawn.
+    let scroll = find_scroll(old, new);
+    if let Some(scroll) = &scroll {
+        w.scroll_rows(scroll.rows.clone(), scroll.amount);
+    }
+
+    // Rows that have changed are copied out one at a time, to find runs in.
+    let mut old_row = Row::new();
+    let mut new_row = Row::new();
+
     for y in 0..new.size().y {
-        for x in 0..new.size().x {
-            let old_cell = old[[x, y]];
-            let new_cell = new[[x, y]];
+        let old_y = match &scroll {
+            Some(scroll) => scroll.source(y),
+            None => Some(y),
+        };
+
+        match old_y {
+            Some(old_y) if rows_eq(old, old_y, new, y) => continue,
+            Some(old_y) => read_row(old, old_y, &mut old_row),
+            None => {
+                old_row.clear();
+                old_row.resize(new.size().x as usize, Some(Cell::default()));
+            }
+        }
+        read_row(new, y, &mut new_row);
 
-            if old_cell == new_cell {
+        let mut x = 0;
+        while x < new_row.len() {
+            if old_row[x] == new_row[x] {
+                x += 1;
                 continue;
             }
 
-            let cell = new_cell.unwrap_or_default();
+            // Covered by a wide cell that has already been drawn.
+            let Some(cell) = new_row[x] else {
+                x += 1;
+                continue;
+            };
 
             draw_style_diff(style, cell.style, w);
             style = cell.style;
 
-            let cell_pos = Vec2::from([x, y]);
+            let cell_pos = Vec2::new(x as u16, y);
             if cell_pos != cursor_pos {
                 w.set_cursor_pos(cell_pos);
                 cursor_pos = cell_pos;
             }
 
-            cursor_pos.x = cursor_pos.x.saturating_add(1);
+            let run = run_len(&new_row, x);
+            let changed = (x..x + run).filter(|&x| old_row[x] != new_row[x]).count();
 
-            w.write_char(cell.c);
+            if is_blank(cell) && x + run == new_row.len() && changed > CLEAR_LINE_MIN {
+                w.clear_line_right();
+                x += run;
+                cells_drawn += changed;
+            } else if is_blank(cell) && changed > ERASE_CHARS_MIN {
+                w.erase_chars(run as u16);
+                x += run;
+                cells_drawn += changed;
+            } else if changed > REPEAT_MIN {
+                w.repeat_str(cell.symbol(), run as u16);
+                cursor_pos.x = cursor_pos.x.saturating_add(run as u16);
+                x += run;
+                cells_drawn += changed;
+            } else {
+                w.write_str(cell.symbol());
+                cursor_pos.x = cursor_pos.x.saturating_add(cell.width());
+                x += 1;
+                cells_drawn += 1;
+            }
         }
     }
 
@@ -52,9 +122,12 @@ pub fn draw_diff(old: &BufferView, new: &BufferView, w: &mut impl Writer) {
             w.set_cursor_vis(false);
         }
     }
+
+    cells_drawn
 }
 
-fn draw_no_diff(buf: &BufferView, w: &mut impl Writer) {
+/// Draw the whole of `buf`, returning how many cells were drawn.
+pub fn draw_no_diff(buf: &BufferView, w: &mut impl Writer) -> usize {
     w.clear_all();
 
     w.set_cursor_home();
@@ -64,12 +137,17 @@ fn draw_no_diff(buf: &BufferView, w: &mut impl Writer) {
     w.write_style(style);
 
     let mut pos_dirty = false;
+    let mut cells_drawn = 0;
 
     for y in 0..buf.size().y {
         for x in 0..buf.size().x {
-            let Some(cell) = buf[[x, y]] else {
+            if buf[[x, y]].is_none() {
                 pos_dirty = true;
                 continue;
+            }
+
+            let Some(cell) = visible_cell(buf, [x, y]) else {
+                continue;
             };
 
             if pos_dirty {
@@ -79,7 +157,9 @@ fn draw_no_diff(buf: &BufferView, w: &mut impl Writer) {
             draw_style_diff(style, cell.style, w);
             style = cell.style;
 
-            w.write_char(cell.c);
+            w.write_str(cell.symbol());
+            pos_dirty = false;
+            cells_drawn += 1;
         }
 
         pos_dirty = true;
@@ -94,9 +174,225 @@ fn draw_no_diff(buf: &BufferView, w: &mut impl Writer) {
             w.set_cursor_vis(false);
         }
     }
+
+    cells_drawn
+}
+
+/// The cell to draw at a position, or `None` if it's covered by the wide cell
+/// before it.
+///
+/// A wide cell whose continuation has been overwritten, or a continuation cell
+/// whose wide cell has been overwritten, is drawn as a blank so that the cells
+/// around it stay in place.
+fn visible_cell(buf: &BufferView, pos: impl Into<Vec2>) -> Option<Cell> {
+    let pos = pos.into();
+    let cell = buf[pos].unwrap_or_default();
+
+    let is_wide = |pos: Vec2| {
+        buf.get(pos)
+            .copied()
+            .flatten()
+            .is_some_and(|cell| cell.width() == 2)
+    };
+    let is_continuation = |pos: Vec2| {
+        buf.get(pos)
+            .copied()
+            .flatten()
+            .is_some_and(|cell| cell.is_continuation())
+    };
+
+    if cell.is_continuation() {
+        if pos.x > 0 && is_wide(Vec2::new(pos.x - 1, pos.y)) {
+            return None;
+        }
+        return Some(Cell::new(' ', cell.style));
+    }
+
+    if cell.width() == 2 && !is_continuation(Vec2::new(pos.x + 1, pos.y)) {
+        return Some(Cell::new(' ', cell.style));
+    }
+
+    Some(cell)
+}
+
+/// Copy the visible cells of a row into `row`.
+fn read_row(buf: &BufferView, y: u16, row: &mut Row) {
+    row.clear();
+    row.extend((0..buf.size().x).map(|x| visible_cell(buf, [x, y])));
+}
+
+/// Whether row `a_y` of `a` holds the same cells as row `b_y` of `b`. How a
+/// cell is drawn only depends on the row it's in, so the rows look the same
+/// too.
+fn rows_eq(a: &BufferView, a_y: u16, b: &BufferView, b_y: u16) -> bool {
+    a.row(a_y) == b.row(b_y)
+}
+
+fn row_hashes(buf: &BufferView) -> Vec<u64> {
+    (0..buf.size().y)
+        .map(|y| {
+            let mut hasher = RowHasher::default();
+            buf.row(y).hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+/// FxHash, which is much quicker than the standard library's hasher, and
+/// doesn't need to resist collision attacks for comparing rows.
+#[derive(Default)]
+struct RowHasher(u64);
+
+impl RowHasher {
+    fn add(&mut self, word: u64) {
+        self.0 = (self.0.rotate_left(5) ^ word).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+}
+
+impl Hasher for RowHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.add(u64::from_le_bytes(word));
+        }
+    }
+
+    fn write_u8(&mut self, n: u8) {
+        self.add(n as u64);
+    }
+
+    fn write_u16(&mut self, n: u16) {
+        self.add(n as u64);
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.add(n as u64);
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.add(n);
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.add(n as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// The number of cells from `x` that are the same narrow cell as the one at
+/// `x`.
+fn run_len(row: &[Option<Cell>], x: usize) -> usize {
+    match row[x] {
+        Some(cell) if cell.width() == 1 => {
+            row[x..].iter().take_while(|&&c| c == Some(cell)).count()
+        }
+        _ => 1,
+    }
+}
+
+/// Whether a cell looks the same as one that's been erased with its background
+/// color.
+fn is_blank(cell: Cell) -> bool {
+    let style = cell.style;
+    cell.symbol() == " "
+        && style.underline == Underline::None
+        && !style.strikethrough
+        && !style.reverse
+}
+
+fn is_blank_row(buf: &BufferView, y: u16) -> bool {
+    (0..buf.size().x).all(|x| visible_cell(buf, [x, y]) == Some(Cell::default()))
+}
+
+/// Rows of the screen to move up (or down, if `amount` is negative).
+#[derive(Debug, PartialEq, Eq)]
+struct Scroll {
+    rows: Range<u16>,
+    amount: i16,
 }
 
-fn draw_style_diff(old: Style, new: Style, w: &mut impl Writer) {
+impl Scroll {
+    /// The row of the old screen that ends up at row `y` once scrolled, or
+    /// `None` if it's one of the rows left blank.
+    fn source(&self, y: u16) -> Option<u16> {
+        if !self.rows.contains(&y) {
+            return Some(y);
+        }
+        y.checked_add_signed(self.amount)
+            .filter(|src| self.rows.contains(src))
+    }
+}
+
+/// Look for a block of rows that has moved up or down, e.g. when a log view
+/// scrolls, picking the shift that saves drawing the most rows.
+fn find_scroll(old: &BufferView, new: &BufferView) -> Option<Scroll> {
+    let height = new.size().y as usize;
+
+    // Rows worth having scrolled into place: ones that would otherwise be drawn.
+    let saves: Vec<bool> = (0..height as u16)
+        .map(|y| !rows_eq(old, y, new, y) && !is_blank_row(new, y))
+        .collect();
+
+    // Most frames change too little for a scroll to be worth it.
+    if saves.iter().filter(|&&saves| saves).count() < SCROLL_MIN {
+        return None;
+    }
+
+    // Rows are compared by hash. A collision only means a worse scroll, as rows
+    // are compared properly when they're drawn.
+    let old = row_hashes(old);
+    let new = row_hashes(new);
+
+    let mut best: Option<(usize, Scroll)> = None;
+
+    for shift in 1..height {
+        for amount in [shift as isize, -(shift as isize)] {
+            let matches = |y: usize| {
+                y.checked_add_signed(amount)
+                    .filter(|&src| src < height)
+                    .is_some_and(|src| old[src] == new[y])
+            };
+
+            let mut y = 0;
+            while y < height {
+                if !matches(y) {
+                    y += 1;
+                    continue;
+                }
+
+                let start = y;
+                while y < height && matches(y) {
+                    y += 1;
+                }
+
+                let saved = saves[start..y].iter().filter(|&&saves| saves).count();
+                if best.as_ref().is_some_and(|(best, _)| saved <= *best) {
+                    continue;
+                }
+
+                // The region takes in the rows that are scrolled out of view.
+                let rows = match amount {
+                    1.. => start..y + shift,
+                    _ => start - shift..y,
+                };
+                let scroll = Scroll {
+                    rows: rows.start as u16..rows.end as u16,
+                    amount: amount as i16,
+                };
+                best = Some((saved, scroll));
+            }
+        }
+    }
+
+    best.filter(|(saved, _)| *saved >= SCROLL_MIN)
+        .map(|(_, scroll)| scroll)
+}
+
+pub(crate) fn draw_style_diff(old: Style, new: Style, w: &mut impl Writer) {
     if new.fg != old.fg {
         w.set_fg_color(new.fg);
     }
@@ -106,7 +402,207 @@ fn draw_style_diff(old: Style, new: Style, w: &mut impl Writer) {
     if new.weight != old.weight {
         w.set_weight(new.weight);
     }
+    if new.italic != old.italic {
+        w.set_italic(new.italic);
+    }
+    if new.strikethrough != old.strikethrough {
+        w.set_strikethrough(new.strikethrough);
+    }
+    if new.reverse != old.reverse {
+        w.set_reverse(new.reverse);
+    }
+    if new.blink != old.blink {
+        w.set_blink(new.blink);
+    }
+    if new.hidden != old.hidden {
+        w.set_hidden(new.hidden);
+    }
     if new.underline != old.underline {
         w.set_underline(new.underline);
     }
+    if new.underline_color != old.underline_color {
+        w.set_underline_color(new.underline_color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{draw_diff, draw_no_diff, draw_style_diff, find_scroll, Scroll};
+    use crate::buffer::Buffer;
+    use crate::platform::ansi::AnsiWriter;
+    use crate::platform::headless::TestWriter;
+    use crate::platform::Writer;
+    use crate::style::{Color, ColorSupport, Style, Underline, Weight};
+
+    #[test]
+    fn wide_cells() {
+        let mut old = Buffer::new([6, 1]);
+        old.view(false).put_str([0, 0], "日本語", Style::default());
+
+        let mut w = TestWriter::new([6, 1]);
+        draw_no_diff(&old.view(false), &mut w);
+        assert_eq!(w.line(0), "日本語");
+
+        // Overwrite half of two wide cells, leaving the other halves behind.
+        let mut new = old.clone();
+        new.view(false).put_str([3, 0], "ab", Style::default());
+
+        draw_diff(&old.view(false), &new.view(false), &mut w);
+        assert_eq!(w.line(0), "日 ab");
+    }
+
+    fn lines(lines: &[&str]) -> Buffer {
+        let mut buf = Buffer::new([10, lines.len() as u16]);
+        for (y, line) in lines.iter().enumerate() {
+            buf.view(false)
+                .put_str([0, y as u16], &format!("{line:10}"), Style::default());
+        }
+        buf
+    }
+
+    /// Draw the change from `old` to `new`, checking that the screen ends up
+    /// right, and return what was written.
+    fn diff(old: &mut Buffer, new: &mut Buffer) -> String {
+        let mut w = TestWriter::new(old.size());
+        draw_no_diff(&old.view(false), &mut w);
+        draw_diff(&old.view(false), &new.view(false), &mut w);
+
+        let mut expected = TestWriter::new(new.size());
+        draw_no_diff(&new.view(false), &mut expected);
+        for y in 0..new.size().y {
+            assert_eq!(w.line(y), expected.line(y));
+        }
+
+        let mut w = AnsiWriter::new(vec![]).with_repeat(true);
+        draw_diff(&old.view(false), &new.view(false), &mut w);
+        w.flush().unwrap();
+        String::from_utf8(w.inner().clone()).unwrap()
+    }
+
+    #[test]
+    fn scroll() {
+        let mut old = lines(&["a", "one", "two", "three", "four"]);
+        let mut up = lines(&["a", "two", "three", "four", "five"]);
+        let mut down = lines(&["zero", "a", "one", "two", "three"]);
+
+        assert_eq!(
+            find_scroll(&old.view(false), &up.view(false)),
+            Some(Scroll {
+                rows: 1..5,
+                amount: 1
+            })
+        );
+        assert_eq!(
+            find_scroll(&old.view(false), &down.view(false)),
+            Some(Scroll {
+                rows: 0..5,
+                amount: -1
+            })
+        );
+
+        assert!(diff(&mut old, &mut up).contains("\x1b[2;5r\x1b[1S\x1b[r"));
+        assert!(diff(&mut old, &mut down).contains("\x1b[1;5r\x1b[1T\x1b[r"));
+
+        // Nothing worth scrolling.
+        let mut changed = lines(&["b", "one", "two", "three", "four"]);
+        assert!(!diff(&mut old, &mut changed).contains('r'));
+    }
+
+    #[test]
+    fn runs() {
+        let mut old = lines(&["abcdefghij", "abcdefghij"]);
+
+        let mut cleared = lines(&["ab", "abcdefghij"]);
+        assert!(diff(&mut old, &mut cleared).ends_with("\x1b[1;3H\x1b[K\x1b[?25l"));
+
+        let mut border = lines(&["a────────b", "abcdefghij"]);
+        assert!(diff(&mut old, &mut border).contains("─\x1b[7b"));
+
+        // Only the cells in a run that actually changed count as drawn.
+        let drawn = |old: &mut Buffer, new: &mut Buffer| {
+            let mut w = TestWriter::new(old.size());
+            draw_diff(&old.view(false), &new.view(false), &mut w)
+        };
+        let mut gap = lines(&["abcd  ghij", "abcdefghij"]);
+        assert!(diff(&mut gap, &mut cleared).contains("\x1b[K"));
+        assert_eq!(drawn(&mut gap, &mut cleared), 6);
+
+        // Seven of the repeated cells, then the "b".
+        let mut line = lines(&["abcdefg─ij", "abcdefghij"]);
+        assert!(diff(&mut line, &mut border).contains("\x1b[7b"));
+        assert_eq!(drawn(&mut line, &mut border), 8);
+
+        // A blank run with a style that erasing would lose.
+        let mut underlined = old.clone();
+        underlined.view(false).put_str(
+            [0, 0],
+            &" ".repeat(10),
+            Style::new().with_underline(Underline::Single),
+        );
+        assert!(!diff(&mut old, &mut underlined).contains('K'));
+    }
+
+    #[test]
+    fn random_diffs() {
+        // A simple xorshift, so that failures can be reproduced.
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move |n: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % n
+        };
+
+        let words = ["", "a", "ab", "日本", "───", "    ", "x y"];
+        let mut random_buf = |prev: Option<&Buffer>| {
+            let mut buf = Buffer::new([12, 6]);
+            let shift = next(5) as i16 - 2;
+            for y in 0..6_u16 {
+                // Mostly take rows from the previous buffer, shifted.
+                if let (Some(prev), 1..) = (prev, next(4)) {
+                    let src = y.saturating_add_signed(shift).min(5);
+                    for x in 0..12 {
+                        buf.view(false)[[x, y]] = *prev.get([x, src]).unwrap();
+                    }
+                    continue;
+                }
+
+                let mut x = 0;
+                while x < 12 {
+                    let style = Style::new().with_italic(next(2) == 0);
+                    x += buf.view(false).put_str(
+                        [x, y],
+                        words[next(words.len() as u64) as usize],
+                        style,
+                    ) + 1;
+                }
+            }
+            buf
+        };
+
+        for _ in 0..200 {
+            let mut old = random_buf(None);
+            let mut new = random_buf(Some(&old));
+            diff(&mut old, &mut new);
+        }
+    }
+
+    #[test]
+    fn style_diff() {
+        let old = Style::new()
+            .with_weight(Weight::Bold)
+            .with_underline(Underline::Double);
+        let new = old.with_italic(true).with_underline_color(Color::Blue);
+
+        // Underline colors are never sent with 16 colors, even if the terminal
+        // supports them.
+        let mut w = AnsiWriter::new(vec![])
+            .with_color_support(ColorSupport::Ansi16)
+            .with_styled_underlines(true);
+        draw_style_diff(old, new, &mut w);
+        draw_style_diff(new, new, &mut w);
+        w.flush().unwrap();
+
+        assert_eq!(String::from_utf8(w.inner().clone()).unwrap(), "\x1b[3m");
+    }
 }
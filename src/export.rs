@@ -0,0 +1,359 @@
+//! Exporting buffers as HTML, SVG and ANSI text, e.g. for screenshots in docs.
+
+use std::fmt::Write as _;
+
+use crate::buffer::Buffer;
+use crate::draw_buffer::draw_style_diff;
+use crate::platform::ansi::AnsiWriter;
+use crate::platform::Writer;
+use crate::style::{ColorSupport, Style, Underline, Weight};
+
+type Rgb = (u8, u8, u8);
+
+/// The colors used for [`Color::Default`](crate::style::Color::Default), as there's no terminal to ask.
+const DEFAULT_FG: Rgb = (229, 229, 229);
+const DEFAULT_BG: Rgb = (0, 0, 0);
+
+/// The size of a cell in an SVG, in pixels, to go with a 15px font.
+const CELL_WIDTH: u16 = 9;
+const CELL_HEIGHT: u16 = 18;
+const FONT_SIZE: u16 = 15;
+const BASELINE: u16 = 14;
+
+/// Cells on a line that have the same style.
+struct Run {
+    x: u16,
+    width: u16,
+    text: String,
+    style: Style,
+}
+
+impl Buffer {
+    /// Export the buffer as a standalone HTML page, with a span for each run of
+    /// cells with the same style.
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+
+        writeln!(html, "<!DOCTYPE html>").unwrap();
+        writeln!(html, "<html>\n<head>\n<meta charset=\"utf-8\">").unwrap();
+        writeln!(
+            html,
+            "<style>pre {{ display: inline-block; margin: 0; padding: 0.5em; \
+             font-family: monospace; color: {}; background: {}; }}</style>",
+            hex(DEFAULT_FG),
+            hex(DEFAULT_BG)
+        )
+        .unwrap();
+        writeln!(html, "</head>\n<body>").unwrap();
+
+        html.push_str("<pre>");
+        for y in 0..self.size().y {
+            for run in self.runs(y) {
+                let css = css(run.style);
+                if css.is_empty() {
+                    html.push_str(&escape(&run.text));
+                } else {
+                    write!(html, "<span style=\"{css}\">{}</span>", escape(&run.text)).unwrap();
+                }
+            }
+            html.push('\n');
+        }
+        html.push_str("</pre>\n");
+
+        writeln!(html, "</body>\n</html>").unwrap();
+        html
+    }
+
+    /// Export the buffer as an SVG image, with a rectangle for each background
+    /// color and monospace text on top.
+    pub fn to_svg(&self) -> String {
+        let size = self.size();
+        let mut svg = String::new();
+
+        writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+             font-family=\"monospace\" font-size=\"{FONT_SIZE}\">",
+            size.x * CELL_WIDTH,
+            size.y * CELL_HEIGHT
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            "<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>",
+            hex(DEFAULT_BG)
+        )
+        .unwrap();
+
+        let runs: Vec<(u16, Run)> = (0..size.y)
+            .flat_map(|y| self.runs(y).into_iter().map(move |run| (y, run)))
+            .collect();
+
+        // Backgrounds go first, so that they don't cover any text.
+        for (y, run) in &runs {
+            let (_, bg) = colors(run.style);
+            if let Some(bg) = bg {
+                writeln!(
+                    svg,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{CELL_HEIGHT}\" fill=\"{}\"/>",
+                    run.x * CELL_WIDTH,
+                    y * CELL_HEIGHT,
+                    run.width * CELL_WIDTH,
+                    hex(bg)
+                )
+                .unwrap();
+            }
+        }
+
+        for (y, run) in &runs {
+            let decorated = run.style.underline != Underline::None || run.style.strikethrough;
+            if run.text.trim().is_empty() && !decorated {
+                continue;
+            }
+
+            let (fg, _) = colors(run.style);
+            write!(
+                svg,
+                "<text x=\"{}\" y=\"{}\" textLength=\"{}\" fill=\"{}\" xml:space=\"preserve\"",
+                run.x * CELL_WIDTH,
+                y * CELL_HEIGHT + BASELINE,
+                run.width * CELL_WIDTH,
+                hex(fg.unwrap_or(DEFAULT_FG))
+            )
+            .unwrap();
+
+            match run.style.weight {
+                Weight::Normal => {}
+                Weight::Bold => svg.push_str(" font-weight=\"bold\""),
+                Weight::Dim => svg.push_str(" opacity=\"0.5\""),
+            }
+            if run.style.italic {
+                svg.push_str(" font-style=\"italic\"");
+            }
+            let decorations = decorations(run.style);
+            if !decorations.is_empty() {
+                write!(svg, " text-decoration=\"{decorations}\"").unwrap();
+            }
+
+            writeln!(svg, ">{}</text>", escape(&run.text)).unwrap();
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Export the buffer as text with ANSI escape sequences for the styles,
+    /// e.g. for printing to a terminal. Colors are written as they are, without
+    /// downsampling.
+    pub fn to_ansi(&self) -> String {
+        let mut w = AnsiWriter::new(vec![])
+            .with_color_support(ColorSupport::TrueColor)
+            .with_styled_underlines(true);
+
+        for y in 0..self.size().y {
+            let mut style = Style::default();
+            for run in self.runs(y) {
+                draw_style_diff(style, run.style, &mut w);
+                style = run.style;
+                w.write_str(&run.text);
+            }
+
+            draw_style_diff(style, Style::default(), &mut w);
+            w.next_line();
+        }
+
+        w.flush().unwrap();
+        String::from_utf8(w.inner().clone()).unwrap()
+    }
+
+    /// Split a line into runs of cells with the same style. Empty cells are
+    /// spaces.
+    fn runs(&self, y: u16) -> Vec<Run> {
+        let mut runs: Vec<Run> = vec![];
+        let mut covered = false;
+
+        for x in 0..self.size().x {
+            let cell = self.get([x, y]).copied().flatten().unwrap_or_default();
+
+            // Skip the second half of a wide cell, which has already been drawn.
+            if std::mem::take(&mut covered) && cell.is_continuation() {
+                continue;
+            }
+
+            let (symbol, width) = match cell.is_continuation() {
+                true => (" ", 1),
+                false => (cell.symbol(), cell.width()),
+            };
+            covered = width == 2;
+
+            match runs.last_mut() {
+                Some(run) if run.style == cell.style => {
+                    run.text.push_str(symbol);
+                    run.width += width;
+                }
+                _ => runs.push(Run {
+                    x,
+                    width,
+                    text: symbol.to_owned(),
+                    style: cell.style,
+                }),
+            }
+        }
+
+        runs
+    }
+}
+
+/// The foreground and background colors to draw a style with, or `None` for
+/// the defaults.
+fn colors(style: Style) -> (Option<Rgb>, Option<Rgb>) {
+    let mut fg = style.fg.to_rgb();
+    let mut bg = style.bg.to_rgb();
+
+    if style.reverse {
+        (fg, bg) = (
+            Some(bg.unwrap_or(DEFAULT_BG)),
+            Some(fg.unwrap_or(DEFAULT_FG)),
+        );
+    }
+    if style.hidden {
+        fg = Some(bg.unwrap_or(DEFAULT_BG));
+    }
+
+    (fg, bg)
+}
+
+/// The CSS for a style, or an empty string for the default style.
+fn css(style: Style) -> String {
+    let mut css = vec![];
+
+    let (fg, bg) = colors(style);
+    if let Some(fg) = fg {
+        css.push(format!("color:{}", hex(fg)));
+    }
+    if let Some(bg) = bg {
+        css.push(format!("background:{}", hex(bg)));
+    }
+
+    match style.weight {
+        Weight::Normal => {}
+        Weight::Bold => css.push("font-weight:bold".to_owned()),
+        Weight::Dim => css.push("opacity:0.5".to_owned()),
+    }
+    if style.italic {
+        css.push("font-style:italic".to_owned());
+    }
+
+    let decorations = decorations(style);
+    if !decorations.is_empty() {
+        css.push(format!("text-decoration:{decorations}"));
+    }
+    let underline_style = match style.underline {
+        Underline::None | Underline::Single => None,
+        Underline::Double => Some("double"),
+        Underline::Curly => Some("wavy"),
+        Underline::Dotted => Some("dotted"),
+        Underline::Dashed => Some("dashed"),
+    };
+    if let Some(underline_style) = underline_style {
+        css.push(format!("text-decoration-style:{underline_style}"));
+    }
+    if let (Some(color), true) = (
+        style.underline_color.to_rgb(),
+        style.underline != Underline::None,
+    ) {
+        css.push(format!("text-decoration-color:{}", hex(color)));
+    }
+
+    css.join(";")
+}
+
+/// The `text-decoration` lines for a style.
+fn decorations(style: Style) -> String {
+    let mut decorations = vec![];
+    if style.underline != Underline::None {
+        decorations.push("underline");
+    }
+    if style.strikethrough {
+        decorations.push("line-through");
+    }
+    decorations.join(" ")
+}
+
+fn hex((r, g, b): Rgb) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// Escape text for HTML or SVG.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::Buffer;
+    use crate::style::{Color, Style, Underline, Weight};
+
+    fn buffer() -> Buffer {
+        let mut buf = Buffer::new([6, 2]);
+        let mut view = buf.view(false);
+        view.put_str([0, 0], "a<b", Style::new().with_fg(Color::Red));
+        view.put_str([3, 0], "日", Style::new().with_bg(Color::Rgb(1, 2, 3)));
+        view.put_str(
+            [0, 1],
+            "ok",
+            Style::new()
+                .with_weight(Weight::Bold)
+                .with_underline(Underline::Curly),
+        );
+        buf
+    }
+
+    #[test]
+    fn html() {
+        let html = buffer().to_html();
+        assert!(html.contains(
+            "<pre><span style=\"color:#cd0000\">a&lt;b</span>\
+             <span style=\"background:#010203\">日</span> \n\
+             <span style=\"font-weight:bold;text-decoration:underline;\
+             text-decoration-style:wavy\">ok</span>    \n</pre>"
+        ));
+    }
+
+    #[test]
+    fn svg() {
+        let svg = buffer().to_svg();
+        assert!(
+            svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"54\" height=\"36\"")
+        );
+        assert!(
+            svg.contains("<rect x=\"27\" y=\"0\" width=\"18\" height=\"18\" fill=\"#010203\"/>")
+        );
+        assert!(svg.contains(
+            "<text x=\"0\" y=\"14\" textLength=\"27\" fill=\"#cd0000\" xml:space=\"preserve\">a&lt;b</text>"
+        ));
+        assert!(svg.contains("font-weight=\"bold\" text-decoration=\"underline\">ok</text>"));
+
+        // Blank runs don't need any text.
+        assert!(!svg.contains(">    </text>"));
+    }
+
+    #[test]
+    fn ansi() {
+        assert_eq!(
+            buffer().to_ansi(),
+            "\x1b[31ma<b\x1b[39m\x1b[48;2;1;2;3m日\x1b[49m \n\
+             \x1b[1m\x1b[4:3mok\x1b[22m\x1b[24m    \n"
+        );
+    }
+}
@@ -3,11 +3,17 @@ pub mod buffer;
 pub mod callback;
 pub mod component;
 pub mod platform;
+pub mod renderer;
+pub mod snapshot;
+pub mod stats;
 pub mod style;
+pub mod task;
+pub mod timer;
 pub mod vec2;
 pub mod views;
 
 mod draw_buffer;
+mod export;
 
 pub mod prelude {
     pub use crate::app::*;
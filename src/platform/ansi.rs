@@ -1,61 +1,247 @@
+use std::env;
 use std::fmt::Write as _;
-use std::io::{self, Write};
+use std::io::{self, IoSlice, Write};
+use std::ops::Range;
 
+use super::asciicast::Recorder;
 use super::Writer;
-use crate::style::{Color, Weight};
+use crate::style::{Color, ColorSupport, Underline, Weight};
 use crate::vec2::Vec2;
 
 const CSI: &str = "\x1b[";
 
+const SYNC_BEGIN: &str = "\x1b[?2026h";
+const SYNC_END: &str = "\x1b[?2026l";
+
 pub struct AnsiWriter<W: Write> {
     buf: String,
     writer: W,
+
+    color_support: ColorSupport,
+    styled_underlines: bool,
+    repeat: bool,
+    synchronized_output: bool,
+
+    /// The row of the screen that positions are relative to, when drawing to an
+    /// inline viewport.
+    origin: Option<u16>,
+
+    recorder: Option<Recorder>,
 }
 
 impl<W: Write> AnsiWriter<W> {
+    /// Create a writer, detecting the color support of the terminal from the
+    /// environment.
     pub fn new(writer: W) -> Self {
         Self {
             buf: String::new(),
             writer,
+
+            color_support: ColorSupport::detect(),
+            styled_underlines: detect_styled_underlines(),
+            repeat: detect_repeat(),
+            synchronized_output: false,
+
+            origin: None,
+
+            recorder: None,
         }
     }
 
+    pub fn with_color_support(mut self, color_support: ColorSupport) -> Self {
+        self.color_support = color_support;
+        self
+    }
+
+    pub fn color_support(&self) -> ColorSupport {
+        self.color_support
+    }
+
+    /// Use double, curly, dotted and dashed underlines, and underline colors.
+    /// Otherwise, underlines are all drawn as single ones in the text color.
+    ///
+    /// By default, this is guessed from the environment.
+    pub fn with_styled_underlines(mut self, enabled: bool) -> Self {
+        self.styled_underlines = enabled;
+        self
+    }
+
+    pub fn styled_underlines(&self) -> bool {
+        self.styled_underlines
+    }
+
+    /// Repeat characters with `CSI n b` (REP). Terminals that don't support it,
+    /// like the Linux console, draw the character once and skip the rest.
+    ///
+    /// By default, this is guessed from the environment.
+    pub fn with_repeat(mut self, enabled: bool) -> Self {
+        self.repeat = enabled;
+        self
+    }
+
+    pub fn repeat(&self) -> bool {
+        self.repeat
+    }
+
+    /// Wrap everything written in a flush in a synchronized update (DEC mode
+    /// 2026), so that the terminal draws it all at once instead of tearing.
+    ///
+    /// Only turn this on if the terminal supports it. Off by default.
+    pub fn with_synchronized_output(mut self, enabled: bool) -> Self {
+        self.synchronized_output = enabled;
+        self
+    }
+
+    pub fn synchronized_output(&self) -> bool {
+        self.synchronized_output
+    }
+
+    /// Draw relative to the given row rather than the whole screen. Clearing
+    /// only clears from that row down.
+    pub(crate) fn set_origin(&mut self, origin: Option<u16>) {
+        self.origin = origin;
+    }
+
+    /// Record everything that's written.
+    pub fn set_recorder(&mut self, recorder: Option<Recorder>) {
+        self.recorder = recorder;
+    }
+
     pub fn inner(&self) -> &W {
         &self.writer
     }
 
+    pub(crate) fn inner_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
     pub fn write(&mut self, mut w: impl io::Write) -> io::Result<()> {
         w.write_all(self.buf.as_bytes())?;
         self.buf.clear();
         Ok(())
     }
+
+    /// Write the SGR parameters for a color, where `base` is 30 for the
+    /// foreground or 40 for the background.
+    fn write_color(&mut self, c: Color, base: u8) {
+        match c.downsample(self.color_support) {
+            Color::Default => write!(self.buf, "{CSI}{}m", base + 9),
+            Color::Indexed(idx) => write!(self.buf, "{CSI}{};5;{idx}m", base + 8),
+            Color::Rgb(r, g, b) => write!(self.buf, "{CSI}{};2;{r};{g};{b}m", base + 8),
+            named => {
+                let idx = named.named_index().unwrap_or_default();
+                if idx < 8 {
+                    write!(self.buf, "{CSI}{}m", base + idx)
+                } else {
+                    write!(self.buf, "{CSI}{}m", base + 60 + idx - 8)
+                }
+            }
+        }
+        .unwrap();
+    }
 }
 
 impl<W: Write> Writer for AnsiWriter<W> {
     fn flush(&mut self) -> io::Result<()> {
-        self.writer.write_all(self.buf.as_bytes())?;
+        let sync = self.synchronized_output && !self.buf.is_empty();
+
+        // Write the frame and the sequences around it together, rather than
+        // moving the whole frame along to make room for them.
+        let (begin, end) = if sync {
+            (SYNC_BEGIN, SYNC_END)
+        } else {
+            ("", "")
+        };
+        write_all_vectored(
+            &mut self.writer,
+            &mut [begin, &self.buf, end].map(|s| IoSlice::new(s.as_bytes())),
+        )?;
+
+        let recorded = match &self.recorder {
+            Some(recorder) if sync => recorder.output(&format!("{begin}{}{end}", self.buf)),
+            Some(recorder) if !self.buf.is_empty() => recorder.output(&self.buf),
+            _ => Ok(()),
+        };
         self.buf.clear();
+        recorded?;
 
         self.writer.flush()
     }
 
+    fn buffered_len(&self) -> usize {
+        match self.buf.len() {
+            0 => 0,
+            len if self.synchronized_output => len + SYNC_BEGIN.len() + SYNC_END.len(),
+            len => len,
+        }
+    }
+
     fn clear_all(&mut self) {
-        write!(self.buf, "{CSI}2J{CSI}3J").unwrap();
+        match self.origin {
+            None => write!(self.buf, "{CSI}2J").unwrap(),
+            Some(_) => {
+                self.set_cursor_home();
+                write!(self.buf, "{CSI}J").unwrap();
+            }
+        }
     }
 
     fn set_cursor_home(&mut self) {
-        write!(self.buf, "{CSI}H").unwrap();
+        match self.origin {
+            None => write!(self.buf, "{CSI}H").unwrap(),
+            Some(_) => self.set_cursor_pos([0, 0]),
+        }
     }
 
     fn set_cursor_pos(&mut self, pos: impl Into<Vec2>) {
         let pos = pos.into();
 
-        let row = pos.y.saturating_add(1);
+        let row = pos
+            .y
+            .saturating_add(self.origin.unwrap_or(0))
+            .saturating_add(1);
         let col = pos.x.saturating_add(1);
 
         write!(self.buf, "{CSI}{row};{col}H").unwrap();
     }
 
+    fn clear_line_right(&mut self) {
+        write!(self.buf, "{CSI}K").unwrap();
+    }
+
+    fn erase_chars(&mut self, n: u16) {
+        write!(self.buf, "{CSI}{n}X").unwrap();
+    }
+
+    fn repeat_str(&mut self, s: &str, n: u16) {
+        // REP repeats a single character, not a whole grapheme cluster.
+        if self.repeat && n > 1 && s.chars().count() == 1 {
+            self.write_str(s);
+            write!(self.buf, "{CSI}{}b", n - 1).unwrap();
+        } else {
+            for _ in 0..n {
+                self.write_str(s);
+            }
+        }
+    }
+
+    fn scroll_rows(&mut self, rows: Range<u16>, amount: i16) {
+        let origin = self.origin.unwrap_or(0);
+        let top = rows.start.saturating_add(origin).saturating_add(1);
+        let bottom = rows.end.saturating_add(origin);
+
+        // Setting the scroll region moves the cursor, so put it somewhere known
+        // once we're done.
+        write!(self.buf, "{CSI}{top};{bottom}r").unwrap();
+        match amount {
+            0 => {}
+            1.. => write!(self.buf, "{CSI}{amount}S").unwrap(),
+            _ => write!(self.buf, "{CSI}{}T", amount.unsigned_abs()).unwrap(),
+        }
+        write!(self.buf, "{CSI}r").unwrap();
+        self.set_cursor_home();
+    }
+
     fn set_cursor_vis(&mut self, vis: bool) {
         match vis {
             true => write!(self.buf, "{CSI}?25h").unwrap(),
@@ -63,16 +249,40 @@ impl<W: Write> Writer for AnsiWriter<W> {
         }
     }
 
+    fn set_alt_screen(&mut self, enabled: bool) {
+        // 1049 also saves and restores the cursor position.
+        match enabled {
+            true => write!(self.buf, "{CSI}?1049h").unwrap(),
+            false => write!(self.buf, "{CSI}?1049l").unwrap(),
+        }
+    }
+
+    fn set_mouse_capture(&mut self, capture: bool) {
+        // Report presses, releases and drags, using the SGR encoding if the terminal
+        // supports it.
+        match capture {
+            true => write!(self.buf, "{CSI}?1000h{CSI}?1002h{CSI}?1006h").unwrap(),
+            false => write!(self.buf, "{CSI}?1006l{CSI}?1002l{CSI}?1000l").unwrap(),
+        }
+    }
+
+    fn set_bracketed_paste(&mut self, enabled: bool) {
+        match enabled {
+            true => write!(self.buf, "{CSI}?2004h").unwrap(),
+            false => write!(self.buf, "{CSI}?2004l").unwrap(),
+        }
+    }
+
     fn next_line(&mut self) {
         self.buf.push('\n');
     }
 
     fn set_fg_color(&mut self, c: Color) {
-        write!(self.buf, "{CSI}3{}m", c as u8).unwrap();
+        self.write_color(c, 30);
     }
 
     fn set_bg_color(&mut self, c: Color) {
-        write!(self.buf, "{CSI}4{}m", c as u8).unwrap();
+        self.write_color(c, 40);
     }
 
     fn set_weight(&mut self, weight: Weight) {
@@ -83,13 +293,75 @@ impl<W: Write> Writer for AnsiWriter<W> {
         }
     }
 
-    fn set_underline(&mut self, underline: bool) {
+    fn set_italic(&mut self, italic: bool) {
+        match italic {
+            true => write!(self.buf, "{CSI}3m").unwrap(),
+            false => write!(self.buf, "{CSI}23m").unwrap(),
+        }
+    }
+
+    fn set_strikethrough(&mut self, strikethrough: bool) {
+        match strikethrough {
+            true => write!(self.buf, "{CSI}9m").unwrap(),
+            false => write!(self.buf, "{CSI}29m").unwrap(),
+        }
+    }
+
+    fn set_reverse(&mut self, reverse: bool) {
+        match reverse {
+            true => write!(self.buf, "{CSI}7m").unwrap(),
+            false => write!(self.buf, "{CSI}27m").unwrap(),
+        }
+    }
+
+    fn set_blink(&mut self, blink: bool) {
+        match blink {
+            true => write!(self.buf, "{CSI}5m").unwrap(),
+            false => write!(self.buf, "{CSI}25m").unwrap(),
+        }
+    }
+
+    fn set_hidden(&mut self, hidden: bool) {
+        match hidden {
+            true => write!(self.buf, "{CSI}8m").unwrap(),
+            false => write!(self.buf, "{CSI}28m").unwrap(),
+        }
+    }
+
+    fn set_underline(&mut self, underline: Underline) {
+        // Plain `4` is understood by more terminals than `4:1`.
         match underline {
-            true => write!(self.buf, "{CSI}4m").unwrap(),
-            false => write!(self.buf, "{CSI}24m").unwrap(),
+            Underline::None => write!(self.buf, "{CSI}24m").unwrap(),
+            Underline::Single => write!(self.buf, "{CSI}4m").unwrap(),
+            _ if !self.styled_underlines => write!(self.buf, "{CSI}4m").unwrap(),
+            Underline::Double => write!(self.buf, "{CSI}4:2m").unwrap(),
+            Underline::Curly => write!(self.buf, "{CSI}4:3m").unwrap(),
+            Underline::Dotted => write!(self.buf, "{CSI}4:4m").unwrap(),
+            Underline::Dashed => write!(self.buf, "{CSI}4:5m").unwrap(),
         }
     }
 
+    fn set_underline_color(&mut self, c: Color) {
+        // Terminals that don't know SGR 58 read its parameters as other attributes,
+        // e.g. `58;5;4` as blinking and underlined.
+        if !self.styled_underlines || self.color_support == ColorSupport::Ansi16 {
+            return;
+        }
+
+        // There are no short forms for underline colors, so named colors use their
+        // palette index.
+        match c.downsample(self.color_support) {
+            Color::Default => write!(self.buf, "{CSI}59m"),
+            Color::Rgb(r, g, b) => write!(self.buf, "{CSI}58;2;{r};{g};{b}m"),
+            Color::Indexed(idx) => write!(self.buf, "{CSI}58;5;{idx}m"),
+            named => {
+                let idx = named.named_index().unwrap_or_default();
+                write!(self.buf, "{CSI}58;5;{idx}m")
+            }
+        }
+        .unwrap();
+    }
+
     fn write_char(&mut self, c: char) {
         if c.is_control() {
             return;
@@ -104,9 +376,218 @@ impl<W: Write> Writer for AnsiWriter<W> {
     }
 
     fn write_style(&mut self, style: crate::style::Style) {
+        // Reset first, so that the underline color only needs sending if it isn't
+        // the default.
+        write!(self.buf, "{CSI}0m").unwrap();
+
         self.set_fg_color(style.fg);
         self.set_bg_color(style.bg);
         self.set_weight(style.weight);
+        self.set_italic(style.italic);
+        self.set_strikethrough(style.strikethrough);
+        self.set_reverse(style.reverse);
+        self.set_blink(style.blink);
+        self.set_hidden(style.hidden);
         self.set_underline(style.underline);
+        if style.underline_color != Color::Default {
+            self.set_underline_color(style.underline_color);
+        }
+    }
+}
+
+/// Like [`Write::write_all`], but for several buffers, so that they can go in
+/// one system call.
+fn write_all_vectored(w: &mut impl Write, mut bufs: &mut [IoSlice<'_>]) -> io::Result<()> {
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        match w.write_vectored(bufs) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(len) => IoSlice::advance_slices(&mut bufs, len),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Guess whether the terminal supports REP, from the environment.
+fn detect_repeat() -> bool {
+    let term = env::var("TERM").ok();
+    let vte_version = env::var("VTE_VERSION").ok();
+    let xterm = env::var_os("XTERM_VERSION").is_some();
+    repeat_from_env(term.as_deref(), vte_version.as_deref(), xterm)
+}
+
+fn repeat_from_env(term: Option<&str>, vte_version: Option<&str>, xterm: bool) -> bool {
+    // Plenty of terminals claim to be xterm, so only trust the variable xterm
+    // itself sets.
+    if xterm || vte_version.and_then(|v| v.parse::<u32>().ok()) >= Some(5400) {
+        return true;
+    }
+
+    matches!(
+        term,
+        Some("xterm-kitty" | "wezterm" | "xterm-ghostty" | "foot" | "foot-extra")
+    )
+}
+
+/// Guess whether the terminal supports styled and colored underlines, from
+/// the environment.
+fn detect_styled_underlines() -> bool {
+    let term = env::var("TERM").ok();
+    let term_program = env::var("TERM_PROGRAM").ok();
+    let vte_version = env::var("VTE_VERSION").ok();
+    styled_underlines_from_env(
+        term.as_deref(),
+        term_program.as_deref(),
+        vte_version.as_deref(),
+    )
+}
+
+fn styled_underlines_from_env(
+    term: Option<&str>,
+    term_program: Option<&str>,
+    vte_version: Option<&str>,
+) -> bool {
+    // VTE has supported them since 0.51.2.
+    if vte_version.and_then(|v| v.parse::<u32>().ok()) >= Some(5102) {
+        return true;
+    }
+
+    if matches!(term_program, Some("WezTerm" | "ghostty" | "vscode")) {
+        return true;
+    }
+
+    matches!(
+        term,
+        Some("xterm-kitty" | "wezterm" | "alacritty" | "xterm-ghostty" | "foot" | "foot-extra")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{repeat_from_env, styled_underlines_from_env, AnsiWriter};
+    use crate::platform::Writer;
+    use crate::style::{Color, ColorSupport, Underline};
+
+    fn colors(color_support: ColorSupport, fg: Color, bg: Color) -> String {
+        let mut w = AnsiWriter::new(vec![]).with_color_support(color_support);
+        w.set_fg_color(fg);
+        w.set_bg_color(bg);
+        w.flush().unwrap();
+
+        String::from_utf8(w.inner().clone()).unwrap()
+    }
+
+    #[test]
+    fn underline_sgr() {
+        let mut w = AnsiWriter::new(vec![])
+            .with_color_support(ColorSupport::TrueColor)
+            .with_styled_underlines(true);
+        w.set_underline(Underline::Curly);
+        w.set_underline_color(Color::Rgb(255, 0, 0));
+        w.set_underline_color(Color::Red);
+        w.set_underline_color(Color::Default);
+        w.set_underline(Underline::None);
+        w.flush().unwrap();
+
+        assert_eq!(
+            String::from_utf8(w.inner().clone()).unwrap(),
+            "\x1b[4:3m\x1b[58;2;255;0;0m\x1b[58;5;1m\x1b[59m\x1b[24m"
+        );
+
+        let mut w = AnsiWriter::new(vec![])
+            .with_color_support(ColorSupport::TrueColor)
+            .with_styled_underlines(false);
+        w.set_underline(Underline::Curly);
+        w.set_underline_color(Color::Red);
+        w.flush().unwrap();
+
+        assert_eq!(String::from_utf8(w.inner().clone()).unwrap(), "\x1b[4m");
+
+        assert!(styled_underlines_from_env(Some("xterm-kitty"), None, None));
+        assert!(styled_underlines_from_env(
+            Some("xterm-256color"),
+            None,
+            Some("7600")
+        ));
+        assert!(!styled_underlines_from_env(
+            Some("xterm-256color"),
+            None,
+            Some("4800")
+        ));
+        assert!(!styled_underlines_from_env(Some("linux"), None, None));
+    }
+
+    #[test]
+    fn repeat() {
+        let mut w = AnsiWriter::new(vec![]).with_repeat(true);
+        w.repeat_str("─", 8);
+        w.repeat_str("e\u{301}", 2);
+        w.flush().unwrap();
+        assert_eq!(
+            String::from_utf8(w.inner().clone()).unwrap(),
+            "─\x1b[7be\u{301}e\u{301}"
+        );
+
+        let mut w = AnsiWriter::new(vec![]).with_repeat(false);
+        w.repeat_str("─", 3);
+        w.flush().unwrap();
+        assert_eq!(String::from_utf8(w.inner().clone()).unwrap(), "───");
+
+        assert!(repeat_from_env(Some("xterm-256color"), None, true));
+        assert!(repeat_from_env(Some("foot"), None, false));
+        assert!(!repeat_from_env(Some("xterm-256color"), None, false));
+        assert!(!repeat_from_env(Some("linux"), None, false));
+    }
+
+    #[test]
+    fn inline_origin() {
+        let mut w = AnsiWriter::new(vec![]);
+        w.set_origin(Some(10));
+        w.clear_all();
+        w.set_cursor_pos([4, 2]);
+        w.flush().unwrap();
+
+        assert_eq!(
+            String::from_utf8(w.inner().clone()).unwrap(),
+            "\x1b[11;1H\x1b[J\x1b[13;5H"
+        );
+    }
+
+    #[test]
+    fn synchronized_output() {
+        let mut w = AnsiWriter::new(vec![]).with_synchronized_output(true);
+        w.flush().unwrap();
+        w.set_cursor_home();
+        assert_eq!(w.buffered_len(), 19);
+        w.flush().unwrap();
+
+        assert_eq!(
+            String::from_utf8(w.inner().clone()).unwrap(),
+            "\x1b[?2026h\x1b[H\x1b[?2026l"
+        );
+    }
+
+    #[test]
+    fn colors_sgr() {
+        use ColorSupport::*;
+
+        assert_eq!(
+            colors(Ansi16, Color::Red, Color::Default),
+            "\x1b[31m\x1b[49m"
+        );
+        assert_eq!(
+            colors(Ansi16, Color::BrightRed, Color::BrightBlack),
+            "\x1b[91m\x1b[100m"
+        );
+        assert_eq!(
+            colors(TrueColor, Color::Indexed(208), Color::Rgb(1, 2, 3)),
+            "\x1b[38;5;208m\x1b[48;2;1;2;3m"
+        );
+        assert_eq!(
+            colors(Ansi256, Color::Rgb(255, 135, 0), Color::Indexed(3)),
+            "\x1b[38;5;208m\x1b[43m"
+        );
     }
 }
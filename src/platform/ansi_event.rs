@@ -1,25 +1,418 @@
+use std::fs::File;
 use std::io;
-use std::time::Instant;
+use std::os::fd::{AsFd, OwnedFd};
+#[cfg(feature = "async")]
+use std::task::{self, Poll};
+use std::time::{Duration, Instant};
 
+use super::asciicast::Recorder;
 use super::event::*;
-use super::input::PollingStdin;
+use super::input::{Input, PollInput, Waker};
+#[cfg(feature = "async")]
+use super::notifier::Notifier;
+use super::Viewport;
+use crate::vec2::Vec2;
+
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// How long to wait for the rest of an escape sequence before giving up and
+/// treating what we have as separate keys.
+pub(super) const DEFAULT_ESC_TIMEOUT: Duration = Duration::from_millis(50);
 
-#[derive(Default)]
 pub struct AnsiEvents {
-    stdin: PollingStdin,
+    // Polls the input's file descriptors, so it has to be dropped first.
+    #[cfg(feature = "async")]
+    notifier: Option<Notifier>,
+
+    input: PollInput,
+    tokenizer: Tokenizer,
+
+    esc_timeout: Duration,
+    last_read: Instant,
+
+    viewport: Viewport,
+    origin: u16,
+
+    /// Whether the last read was cut short by a waker.
+    #[cfg(feature = "async")]
+    woken: bool,
+
+    recorder: Option<Recorder>,
+}
+
+impl AnsiEvents {
+    /// Read events from something other than stdin, such as `/dev/tty`.
+    pub(crate) fn with_input(input: File) -> io::Result<Self> {
+        Ok(Self {
+            #[cfg(feature = "async")]
+            notifier: None,
+
+            input: PollInput::new(input)?,
+            tokenizer: Tokenizer::default(),
+
+            esc_timeout: DEFAULT_ESC_TIMEOUT,
+            last_read: Instant::now(),
+
+            viewport: Viewport::Fullscreen,
+            origin: 0,
+
+            #[cfg(feature = "async")]
+            woken: false,
+
+            recorder: None,
+        })
+    }
 }
 
 impl Events for AnsiEvents {
     fn new() -> io::Result<Self> {
-        Ok(Self::default())
+        let stdin: OwnedFd = io::stdin().as_fd().try_clone_to_owned()?;
+        Self::with_input(stdin.into())
+    }
+
+    fn read_with_deadline(&mut self, deadline: Option<Instant>) -> io::Result<Option<Event>> {
+        loop {
+            if let Some(event) = self.tokenizer.next_event() {
+                match self.relative_to_viewport(event) {
+                    Some(event) => return Ok(Some(event)),
+                    None => continue,
+                }
+            }
+
+            let flush_at = self.flush_at();
+            let read_deadline = earliest(deadline, flush_at);
+
+            match self.input.read_with_deadline(read_deadline)? {
+                Some(Input::Bytes(bytes)) => {
+                    if let Some(recorder) = &self.recorder {
+                        recorder.input(bytes.as_slice())?;
+                    }
+                    self.tokenizer.feed(bytes.as_slice());
+                    self.last_read = Instant::now();
+                }
+
+                Some(Input::Event(event)) => {
+                    if let Event::Resize(size) = event {
+                        if let Some(recorder) = &self.recorder {
+                            recorder.resize(size)?;
+                        }
+                        self.fit_viewport(size);
+                    }
+                    if let Some(event) = self.relative_to_viewport(event) {
+                        return Ok(Some(event));
+                    }
+                }
+
+                Some(Input::Wake) => {
+                    #[cfg(feature = "async")]
+                    {
+                        self.woken = true;
+                    }
+                    return Ok(None);
+                }
+
+                None => {
+                    return match flush_at {
+                        Some(flush_at) if Instant::now() >= flush_at => Ok(self
+                            .tokenizer
+                            .flush()
+                            .and_then(|event| self.relative_to_viewport(event))),
+                        _ => Ok(None),
+                    };
+                }
+            }
+        }
+    }
+
+    fn waker(&self) -> EventWaker {
+        let waker = self.input.waker();
+        EventWaker::new(move || waker.wake())
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncEvents for AnsiEvents {
+    fn poll_event(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        deadline: Option<Instant>,
+    ) -> Poll<io::Result<Option<Event>>> {
+        // Take whatever has already arrived, without blocking.
+        let now = Instant::now();
+        self.woken = false;
+        if let Some(event) = self.read_with_deadline(Some(now))? {
+            return Poll::Ready(Ok(Some(event)));
+        }
+        if self.woken || deadline.is_some_and(|deadline| now >= deadline) {
+            return Poll::Ready(Ok(None));
+        }
+
+        let wake_at = earliest(deadline, self.flush_at());
+
+        let notifier = match &mut self.notifier {
+            Some(notifier) => notifier,
+            None => self
+                .notifier
+                .insert(Notifier::new(self.input.fds().to_vec())?),
+        };
+        notifier.notify(cx.waker().clone(), wake_at);
+
+        Poll::Pending
+    }
+}
+
+impl AnsiEvents {
+    /// The waker for the input's poll, which the resize signal handler writes
+    /// to.
+    pub(crate) fn input_waker(&self) -> Waker {
+        self.input.waker()
+    }
+
+    /// If half a sequence is buffered, when to stop waiting for the rest of it.
+    fn flush_at(&self) -> Option<Instant> {
+        self.tokenizer
+            .is_pending()
+            .then(|| self.last_read + self.esc_timeout)
+    }
+
+    /// Report mouse positions and sizes relative to the viewport, which starts at
+    /// row `origin` of the screen.
+    pub(crate) fn set_viewport(&mut self, viewport: Viewport, origin: u16) {
+        self.viewport = viewport;
+        self.origin = origin;
+    }
+
+    /// The row of the screen the viewport starts on.
+    pub(crate) fn origin(&self) -> u16 {
+        self.origin
+    }
+
+    /// Move an inline viewport up if the screen has shrunk, so that it's still
+    /// on the screen.
+    fn fit_viewport(&mut self, term_size: Vec2) {
+        let height = self.viewport.size(term_size).y;
+        self.origin = self.origin.min(term_size.y - height);
+    }
+
+    /// Make an event relative to the viewport. Mouse events outside of it are
+    /// dropped.
+    fn relative_to_viewport(&self, event: Event) -> Option<Event> {
+        match event {
+            Event::Mouse(mut mouse) => {
+                mouse.pos.y = mouse.pos.y.checked_sub(self.origin)?;
+                if let Viewport::Inline(height) = self.viewport {
+                    if mouse.pos.y >= height {
+                        return None;
+                    }
+                }
+                Some(Event::Mouse(mouse))
+            }
+            Event::Resize(size) => Some(Event::Resize(self.viewport.size(size))),
+            event => Some(event),
+        }
+    }
+
+    /// Set how long to wait for the rest of an escape sequence.
+    ///
+    /// This is what distinguishes pressing escape followed by another key from
+    /// pressing that key with alt held.
+    pub fn set_esc_timeout(&mut self, esc_timeout: Duration) {
+        self.esc_timeout = esc_timeout;
+    }
+
+    /// Record the input that's read.
+    pub fn set_recorder(&mut self, recorder: Option<Recorder>) {
+        self.recorder = recorder;
     }
+}
+
+/// Splits a stream of bytes into events.
+///
+/// Bytes can be fed in however they were read: sequences split across reads
+/// are buffered until the rest of them arrives, and reads containing several
+/// keypresses produce several events.
+#[derive(Default)]
+pub(crate) struct Tokenizer {
+    buf: Vec<u8>,
+
+    /// Whether we're in the middle of a bracketed paste.
+    pasting: bool,
+
+    /// How much of `buf` has already been searched for the end of a paste.
+    paste_searched: usize,
+}
+
+/// The result of scanning the start of the buffer for a complete sequence.
+enum Scan {
+    /// A sequence of the given length.
+    Complete(usize),
+
+    /// The buffer ends partway through a sequence.
+    Incomplete,
+}
 
-    fn read_with_deadline(&mut self, deadline: Instant) -> io::Result<Option<Event>> {
-        let Some(bytes) = self.stdin.read_with_deadline(deadline)? else {
-            return Ok(None);
+impl Tokenizer {
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Take the next event out of the buffer, if a complete one is available.
+    pub fn next_event(&mut self) -> Option<Event> {
+        if self.pasting {
+            return self.finish_paste();
+        }
+
+        let Scan::Complete(len) = scan(&self.buf) else {
+            return None;
         };
-        let event = parse_event(bytes.as_slice()).unwrap_or(Event::Unknown);
-        Ok(Some(event))
+
+        if &self.buf[..len] == PASTE_START {
+            self.buf.drain(..len);
+            self.pasting = true;
+            self.paste_searched = 0;
+
+            return self.finish_paste();
+        }
+
+        let event = parse_event(&self.buf[..len]).unwrap_or(Event::Unknown);
+        self.buf.drain(..len);
+
+        Some(event)
+    }
+
+    /// Whether the buffer ends with an incomplete sequence that could be
+    /// flushed.
+    ///
+    /// Pastes are never flushed, as there's no way to tell how long they'll
+    /// take to arrive.
+    pub fn is_pending(&self) -> bool {
+        !self.pasting && !self.buf.is_empty()
+    }
+
+    /// Give up waiting for the rest of a sequence, and make what we can of the
+    /// bytes we have.
+    pub fn flush(&mut self) -> Option<Event> {
+        if !self.is_pending() {
+            return None;
+        }
+
+        let event = parse_event(&self.buf).unwrap_or(Event::Unknown);
+        self.buf.clear();
+
+        Some(event)
+    }
+
+    /// Take the pasted text out of the buffer if the end of the paste has
+    /// arrived.
+    fn finish_paste(&mut self) -> Option<Event> {
+        // The end marker might have been split across reads, so back up a bit.
+        let start = self.paste_searched.saturating_sub(PASTE_END.len() - 1);
+        self.paste_searched = self.buf.len();
+
+        let end = start + find(&self.buf[start..], PASTE_END)?;
+
+        let text = String::from_utf8_lossy(&self.buf[..end]).into_owned();
+        self.buf.drain(..end + PASTE_END.len());
+        self.pasting = false;
+
+        Some(Event::Paste(text))
+    }
+}
+
+/// The earlier of two deadlines, where `None` means there isn't one.
+fn earliest(a: Option<Instant>, b: Option<Instant>) -> Option<Instant> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Find the length of the sequence at the start of `bytes`.
+///
+/// This only works out where sequences end; `parse_event` decides what they
+/// mean. An empty buffer counts as incomplete.
+fn scan(bytes: &[u8]) -> Scan {
+    match bytes {
+        [] | [b'\x1b'] => Scan::Incomplete,
+
+        // X10 mouse reports are followed by three raw bytes.
+        [b'\x1b', b'[', b'M', rest @ ..] => {
+            if rest.len() >= 3 {
+                Scan::Complete(6)
+            } else {
+                Scan::Incomplete
+            }
+        }
+
+        // CSI sequence: parameter and intermediate bytes followed by a final byte.
+        [b'\x1b', b'[', rest @ ..] => {
+            for (i, &byte) in rest.iter().enumerate() {
+                match byte {
+                    0x20..=0x3f => {}
+                    0x40..=0x7e => return Scan::Complete(i + 3),
+
+                    // Not a valid sequence, so cut it off here.
+                    _ => return Scan::Complete(i + 2),
+                }
+            }
+            Scan::Incomplete
+        }
+
+        // SS3 sequence.
+        [b'\x1b', b'O', rest @ ..] => {
+            if rest.is_empty() {
+                Scan::Incomplete
+            } else {
+                Scan::Complete(3)
+            }
+        }
+
+        // Two escapes in a row are two separate presses.
+        [b'\x1b', b'\x1b', ..] => Scan::Complete(1),
+
+        // Alt and a character.
+        [b'\x1b', rest @ ..] => match scan_char(rest) {
+            Scan::Complete(len) => Scan::Complete(len + 1),
+            Scan::Incomplete => Scan::Incomplete,
+        },
+
+        _ => scan_char(bytes),
+    }
+}
+
+/// Find the length of the utf-8 character at the start of `bytes`.
+///
+/// Invalid bytes are returned one at a time.
+fn scan_char(bytes: &[u8]) -> Scan {
+    let Some(&first) = bytes.first() else {
+        return Scan::Incomplete;
+    };
+
+    let len = match first {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Scan::Complete(1),
+    };
+
+    // Give up early if something other than a continuation byte turns up.
+    let available = &bytes[1..len.min(bytes.len())];
+    if available.iter().any(|&byte| byte & 0xc0 != 0x80) {
+        return Scan::Complete(1);
+    }
+
+    if bytes.len() < len {
+        Scan::Incomplete
+    } else {
+        Scan::Complete(len)
     }
 }
 
@@ -36,6 +429,14 @@ pub fn parse_event(bytes: &[u8]) -> Option<Event> {
                     modifiers: Modifiers::ALT,
                 }),
 
+                // SGR (1006) mouse report.
+                [b'[', b'<', rest @ .., final_byte @ (b'M' | b'm')] => {
+                    Event::Mouse(parse_sgr_mouse(rest, *final_byte == b'm')?)
+                }
+
+                // Legacy X10 mouse report.
+                [b'[', b'M', cb, cx, cy] => Event::Mouse(parse_x10_mouse(*cb, *cx, *cy)?),
+
                 // VT sequence.
                 [b'[', rest @ .., b'~'] => {
                     let (key_code, modifiers) =
@@ -117,13 +518,42 @@ pub fn parse_event(bytes: &[u8]) -> Option<Event> {
                     })
                 }
 
+                // SS3 sequence.
+                [b'O', key_code] => {
+                    let key_code = match key_code {
+                        b'A' => KeyCode::Up,
+                        b'B' => KeyCode::Down,
+                        b'C' => KeyCode::Right,
+                        b'D' => KeyCode::Left,
+
+                        b'F' => KeyCode::End,
+                        b'H' => KeyCode::Home,
+
+                        b'P' => KeyCode::Fn(1),
+                        b'Q' => KeyCode::Fn(2),
+                        b'R' => KeyCode::Fn(3),
+                        b'S' => KeyCode::Fn(4),
+
+                        _ => return None,
+                    };
+
+                    Event::just_key(key_code)
+                }
+
                 [c] => {
                     let mut key_event = decode_byte(*c);
                     key_event.modifiers |= Modifiers::ALT;
                     Event::Key(key_event)
                 }
 
-                _ => return None,
+                // Alt and a multi-byte character.
+                _ => match decode_bytes(rest)? {
+                    Event::Key(mut key_event) => {
+                        key_event.modifiers |= Modifiers::ALT;
+                        Event::Key(key_event)
+                    }
+                    _ => return None,
+                },
             }
         }
 
@@ -140,6 +570,97 @@ fn parse_modifiers(bytes: &[u8]) -> Option<Modifiers> {
         .map(|byte| Modifiers::from_bits_truncate(byte.saturating_sub(1)))
 }
 
+/// Parse the body of an SGR mouse report, i.e. `Cb;Cx;Cy` without the leading
+/// `<` or the final `M`/`m`.
+fn parse_sgr_mouse(bytes: &[u8], released: bool) -> Option<MouseEvent> {
+    let s = std::str::from_utf8(bytes).ok()?;
+    let mut params = s.split(';').map(|param| param.parse::<u16>().ok());
+
+    let cb = params.next()??;
+    let x = params.next()??;
+    let y = params.next()??;
+
+    if params.next().is_some() {
+        return None;
+    }
+
+    // SGR coordinates are 1-based.
+    let pos = Vec2::new(x.saturating_sub(1), y.saturating_sub(1));
+
+    decode_mouse(cb, pos, released)
+}
+
+/// Parse an X10 mouse report, where each of the parameters is a single byte
+/// offset by 32.
+fn parse_x10_mouse(cb: u8, cx: u8, cy: u8) -> Option<MouseEvent> {
+    let cb = cb.checked_sub(32)?;
+
+    // X10 coordinates are also 1-based, hence the extra 1.
+    let x = cx.checked_sub(33)?;
+    let y = cy.checked_sub(33)?;
+
+    decode_mouse(cb as u16, Vec2::new(x as u16, y as u16), false)
+}
+
+/// Decode the button byte of a mouse report, returning `None` for buttons that
+/// aren't supported.
+fn decode_mouse(cb: u16, pos: Vec2, released: bool) -> Option<MouseEvent> {
+    let mut modifiers = Modifiers::empty();
+    if cb & 4 != 0 {
+        modifiers |= Modifiers::SHIFT;
+    }
+    if cb & 8 != 0 {
+        modifiers |= Modifiers::ALT;
+    }
+    if cb & 16 != 0 {
+        modifiers |= Modifiers::CTRL;
+    }
+
+    let button = if cb & 128 != 0 {
+        // Buttons 8 to 11. Only the first two, back and forward, are common, and
+        // none of them scroll.
+        match cb & 0b1100_0011 {
+            128 => Some(MouseButton::Back),
+            129 => Some(MouseButton::Forward),
+            _ => return None,
+        }
+    } else {
+        match cb & 0b11 {
+            0 => Some(MouseButton::Left),
+            1 => Some(MouseButton::Middle),
+            2 => Some(MouseButton::Right),
+            _ => None,
+        }
+    };
+
+    let (kind, button) = if cb & 64 != 0 {
+        let kind = match cb & 0b11 {
+            0 => MouseEventKind::ScrollUp,
+            1 => MouseEventKind::ScrollDown,
+            2 => MouseEventKind::ScrollLeft,
+            _ => MouseEventKind::ScrollRight,
+        };
+        (kind, None)
+    } else if cb & 32 != 0 {
+        match button {
+            Some(_) => (MouseEventKind::Drag, button),
+            None => (MouseEventKind::Move, None),
+        }
+    } else if released || button.is_none() {
+        // X10 reports all releases as button 3.
+        (MouseEventKind::Release, button)
+    } else {
+        (MouseEventKind::Press, button)
+    };
+
+    Some(MouseEvent {
+        kind,
+        button,
+        modifiers,
+        pos,
+    })
+}
+
 /// Decode a character, handling the control keys, control characters* and
 /// utf-8.
 ///
@@ -147,7 +668,7 @@ fn parse_modifiers(bytes: &[u8]) -> Option<Modifiers> {
 fn decode_bytes(bytes: &[u8]) -> Option<Event> {
     match bytes {
         [] => None,
-        [b] => Some(Event::Key(decode_byte(*b))),
+        [b] if b.is_ascii() => Some(Event::Key(decode_byte(*b))),
         _ => {
             let s = std::str::from_utf8(bytes).ok()?;
             let kind = if s.chars().nth(1).is_some() {
@@ -180,3 +701,212 @@ fn decode_byte(byte: u8) -> KeyEvent {
         _ => KeyEvent::key(KeyCode::Char(byte as char)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key_code: KeyCode, modifiers: Modifiers) -> Event {
+        Event::Key(KeyEvent {
+            key_code,
+            modifiers,
+        })
+    }
+
+    /// Feed the chunks in order, then flush.
+    fn tokenize(chunks: &[&[u8]]) -> Vec<Event> {
+        let mut tokenizer = Tokenizer::default();
+        let mut events = vec![];
+
+        for chunk in chunks {
+            tokenizer.feed(chunk);
+            while let Some(event) = tokenizer.next_event() {
+                events.push(event);
+            }
+        }
+        events.extend(tokenizer.flush());
+
+        events
+    }
+
+    #[test]
+    fn tokenize_split_at_every_position() {
+        let stream: &[u8] = b"a\xc3\xa9\xe6\x97\xa5\x01\x1b[A\x1b[1;5C\x1b[15~\x1bx\x1bOP\
+            \x1b[<0;10;5M\x1b[M\x21\x2a\x25\x1b[200~hi\x1b[A\r\n\x1b[201~\x7f\x1b";
+
+        let expected = vec![
+            key(KeyCode::Char('a'), Modifiers::empty()),
+            key(KeyCode::Char('é'), Modifiers::empty()),
+            key(KeyCode::Char('日'), Modifiers::empty()),
+            key(KeyCode::Char('A'), Modifiers::CTRL),
+            key(KeyCode::Up, Modifiers::empty()),
+            key(KeyCode::Right, Modifiers::CTRL),
+            key(KeyCode::Fn(5), Modifiers::empty()),
+            key(KeyCode::Char('x'), Modifiers::ALT),
+            key(KeyCode::Fn(1), Modifiers::empty()),
+            parse_event(b"\x1b[<0;10;5M").unwrap(),
+            parse_event(b"\x1b[M\x21\x2a\x25").unwrap(),
+            Event::Paste("hi\x1b[A\r\n".to_owned()),
+            key(KeyCode::Backspace, Modifiers::empty()),
+            key(KeyCode::Escape, Modifiers::empty()),
+        ];
+
+        assert_eq!(tokenize(&[stream]), expected);
+
+        for split in 0..=stream.len() {
+            let (a, b) = stream.split_at(split);
+            assert_eq!(tokenize(&[a, b]), expected, "split at {split}");
+        }
+
+        let bytes: Vec<&[u8]> = stream.chunks(1).collect();
+        assert_eq!(tokenize(&bytes), expected, "one byte at a time");
+    }
+
+    #[test]
+    fn tokenize_flush() {
+        let mut tokenizer = Tokenizer::default();
+
+        tokenizer.feed(b"\x1b");
+        assert_eq!(tokenizer.next_event(), None);
+        assert!(tokenizer.is_pending());
+        assert_eq!(
+            tokenizer.flush(),
+            Some(key(KeyCode::Escape, Modifiers::empty()))
+        );
+        assert!(!tokenizer.is_pending());
+
+        tokenizer.feed(b"\x1b[");
+        assert_eq!(tokenizer.next_event(), None);
+        assert_eq!(
+            tokenizer.flush(),
+            Some(key(KeyCode::Char('['), Modifiers::ALT))
+        );
+
+        // Pastes wait as long as they need to.
+        tokenizer.feed(b"\x1b[200~abc");
+        assert_eq!(tokenizer.next_event(), None);
+        assert!(!tokenizer.is_pending());
+        assert_eq!(tokenizer.flush(), None);
+    }
+
+    #[test]
+    fn tokenize_invalid() {
+        assert_eq!(
+            tokenize(&[b"\xffa\x1b[\x01"]),
+            vec![
+                Event::Unknown,
+                key(KeyCode::Char('a'), Modifiers::empty()),
+                key(KeyCode::Char('['), Modifiers::ALT),
+                key(KeyCode::Char('A'), Modifiers::CTRL),
+            ]
+        );
+    }
+
+    fn mouse(bytes: &[u8]) -> MouseEvent {
+        match parse_event(bytes) {
+            Some(Event::Mouse(event)) => event,
+            other => panic!("expected mouse event, found {other:?}"),
+        }
+    }
+
+    #[test]
+    fn sgr_mouse() {
+        let event = mouse(b"\x1b[<0;10;5M");
+        assert_eq!(event.kind, MouseEventKind::Press);
+        assert_eq!(event.button, Some(MouseButton::Left));
+        assert_eq!(event.pos, Vec2::new(9, 4));
+        assert!(event.modifiers.is_empty());
+
+        let event = mouse(b"\x1b[<2;300;1m");
+        assert_eq!(event.kind, MouseEventKind::Release);
+        assert_eq!(event.button, Some(MouseButton::Right));
+        assert_eq!(event.pos, Vec2::new(299, 0));
+
+        let event = mouse(b"\x1b[<52;1;1M");
+        assert_eq!(event.kind, MouseEventKind::Drag);
+        assert_eq!(event.button, Some(MouseButton::Left));
+        assert_eq!(event.modifiers, Modifiers::SHIFT | Modifiers::CTRL);
+
+        let event = mouse(b"\x1b[<65;3;4M");
+        assert_eq!(event.kind, MouseEventKind::ScrollDown);
+        assert_eq!(event.button, None);
+
+        let event = mouse(b"\x1b[<128;1;1M");
+        assert_eq!(event.kind, MouseEventKind::Press);
+        assert_eq!(event.button, Some(MouseButton::Back));
+
+        let event = mouse(b"\x1b[<161;1;1M");
+        assert_eq!(event.kind, MouseEventKind::Drag);
+        assert_eq!(event.button, Some(MouseButton::Forward));
+
+        assert!(parse_event(b"\x1b[<130;1;1M").is_none());
+        assert!(parse_event(b"\x1b[<0;10M").is_none());
+    }
+
+    #[test]
+    fn x10_mouse() {
+        let event = mouse(b"\x1b[M\x21\x2a\x25");
+        assert_eq!(event.kind, MouseEventKind::Press);
+        assert_eq!(event.button, Some(MouseButton::Middle));
+        assert_eq!(event.pos, Vec2::new(9, 4));
+
+        let event = mouse(b"\x1b[M\x23\x21\x21");
+        assert_eq!(event.kind, MouseEventKind::Release);
+        assert_eq!(event.button, None);
+        assert_eq!(event.pos, Vec2::new(0, 0));
+
+        let event = mouse(b"\x1b[M\x68\x21\x21");
+        assert_eq!(event.kind, MouseEventKind::ScrollUp);
+        assert_eq!(event.modifiers, Modifiers::ALT);
+    }
+
+    #[test]
+    fn fit_viewport() {
+        let (read, _write) = crate::platform::input::pipe().unwrap();
+        let mut events = AnsiEvents::with_input(read).unwrap();
+        events.set_viewport(Viewport::Inline(5), 20);
+
+        events.fit_viewport(Vec2::new(80, 30));
+        assert_eq!(events.origin(), 20);
+
+        events.fit_viewport(Vec2::new(80, 10));
+        assert_eq!(events.origin(), 5);
+
+        events.fit_viewport(Vec2::new(80, 3));
+        assert_eq!(events.origin(), 0);
+
+        let event = events.relative_to_viewport(Event::Resize(Vec2::new(80, 3)));
+        assert_eq!(event, Some(Event::Resize(Vec2::new(80, 3))));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn poll_event() {
+        use std::io::Write;
+        use std::thread;
+
+        use futures::{future, StreamExt};
+
+        let (read, mut write) = crate::platform::input::pipe().unwrap();
+        let mut events = AnsiEvents::with_input(read).unwrap();
+
+        // Nothing arrives, so we're woken at the deadline.
+        let deadline = Instant::now() + Duration::from_millis(20);
+        let event = futures::executor::block_on(future::poll_fn(|cx| {
+            events.poll_event(cx, Some(deadline))
+        }));
+        assert_eq!(event.unwrap(), None);
+        assert!(Instant::now() >= deadline);
+
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            write.write_all(b"a").unwrap();
+            write
+        });
+
+        let event = futures::executor::block_on(events.stream().next());
+        assert_eq!(event.unwrap().unwrap(), Event::just_key(KeyCode::Char('a')));
+
+        drop(writer.join());
+    }
+}
@@ -0,0 +1,604 @@
+//! Recording sessions to [asciicast v2] files, and replaying their input.
+//!
+//! [asciicast v2]: https://docs.asciinema.org/manual/asciicast/v2/
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use super::ansi_event::{Tokenizer, DEFAULT_ESC_TIMEOUT};
+use super::event::{Event, Events};
+use crate::vec2::Vec2;
+
+/// Writes what's drawn and what's typed to an asciicast file, with the time
+/// since recording started.
+///
+/// It's cheap to clone, and clones write to the same file.
+#[derive(Clone)]
+pub struct Recorder {
+    inner: Arc<Mutex<RecorderInner>>,
+}
+
+struct RecorderInner {
+    out: Box<dyn Write + Send>,
+    start: Instant,
+
+    /// The start of a character that was split between reads.
+    partial_input: Vec<u8>,
+}
+
+impl Recorder {
+    /// Start a recording of a terminal of the given size, writing the header
+    /// straight away.
+    pub fn new(mut out: impl Write + Send + 'static, size: impl Into<Vec2>) -> io::Result<Self> {
+        let size = size.into();
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+
+        writeln!(
+            out,
+            r#"{{"version": 2, "width": {}, "height": {}, "timestamp": {timestamp}}}"#,
+            size.x, size.y
+        )?;
+        out.flush()?;
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(RecorderInner {
+                out: Box::new(out),
+                start: Instant::now(),
+                partial_input: vec![],
+            })),
+        })
+    }
+
+    /// Record bytes written to the terminal.
+    pub fn output(&self, data: &str) -> io::Result<()> {
+        self.event("o", data)
+    }
+
+    /// Record bytes read from the terminal. Invalid UTF-8 is replaced, as
+    /// asciicast can only hold text.
+    pub fn input(&self, data: &[u8]) -> io::Result<()> {
+        let mut inner = self.lock();
+
+        let mut bytes = std::mem::take(&mut inner.partial_input);
+        bytes.extend_from_slice(data);
+
+        // Hold on to a split character until the rest of it is read.
+        let partial_len = partial_char_len(&bytes);
+        inner.partial_input = bytes.split_off(bytes.len() - partial_len);
+
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        inner.event("i", &String::from_utf8_lossy(&bytes))
+    }
+
+    pub fn resize(&self, size: Vec2) -> io::Result<()> {
+        self.event("r", &format!("{}x{}", size.x, size.y))
+    }
+
+    fn event(&self, code: &str, data: &str) -> io::Result<()> {
+        self.lock().event(code, data)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, RecorderInner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl RecorderInner {
+    fn event(&mut self, code: &str, data: &str) -> io::Result<()> {
+        let time = self.start.elapsed().as_secs_f64();
+
+        // Flush as we go, so that a crash doesn't lose the end of the recording.
+        writeln!(self.out, "[{time:.6}, \"{code}\", {}]", json_string(data))?;
+        self.out.flush()
+    }
+}
+
+/// The length of the incomplete UTF-8 character at the end of `bytes`, if
+/// there is one.
+fn partial_char_len(bytes: &[u8]) -> usize {
+    // Find where the last character starts. It's at most 4 bytes long, so an
+    // incomplete one is at most 3.
+    for len in 1..=bytes.len().min(3) {
+        let tail = &bytes[bytes.len() - len..];
+        if tail[0] & 0b1100_0000 != 0b1000_0000 {
+            return match std::str::from_utf8(tail) {
+                Err(err) if err.error_len().is_none() => len,
+                _ => 0,
+            };
+        }
+    }
+    0
+}
+
+/// Events read from the input of an asciicast recording.
+///
+/// Mouse positions and sizes are as the terminal reported them, so replay into
+/// the same kind of viewport that was recorded. Once the recording runs out,
+/// reads return `None` straight away.
+#[derive(Default)]
+pub struct ReplayEvents {
+    events: VecDeque<(Duration, Event)>,
+
+    timing: bool,
+    start: Option<Instant>,
+}
+
+impl ReplayEvents {
+    /// Read the input and resize events from a recording.
+    pub fn from_reader(reader: impl BufRead) -> io::Result<Self> {
+        let mut replay = Self::default();
+
+        let mut tokenizer = Tokenizer::default();
+        let mut last_input = Duration::ZERO;
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+
+            // Skip the header.
+            if line.is_empty() || line.starts_with('{') {
+                continue;
+            }
+
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid asciicast event on line {}", i + 1),
+                )
+            };
+            let (time, code, data) = parse_event(line).ok_or_else(invalid)?;
+
+            // Split up escape sequences the way a live read would have.
+            if time.saturating_sub(last_input) >= DEFAULT_ESC_TIMEOUT {
+                replay.extend(last_input, tokenizer.flush());
+            }
+
+            match code.as_str() {
+                "i" => {
+                    tokenizer.feed(data.as_bytes());
+                    replay.extend(time, std::iter::from_fn(|| tokenizer.next_event()));
+                    last_input = time;
+                }
+                "r" => {
+                    let (width, height) = data.split_once('x').ok_or_else(invalid)?;
+                    let size = Vec2::new(
+                        width.parse().map_err(|_| invalid())?,
+                        height.parse().map_err(|_| invalid())?,
+                    );
+
+                    // Anything typed before the resize comes before it.
+                    replay.extend(last_input, tokenizer.flush());
+                    replay.extend(time, Some(Event::Resize(size)));
+                }
+                _ => {}
+            }
+        }
+
+        replay.extend(last_input, tokenizer.flush());
+
+        Ok(replay)
+    }
+
+    /// Deliver events at the times they were recorded, rather than all at
+    /// once. Off by default.
+    pub fn with_timing(mut self, timing: bool) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    fn extend(&mut self, time: Duration, events: impl IntoIterator<Item = Event>) {
+        self.events
+            .extend(events.into_iter().map(|event| (time, event)));
+    }
+}
+
+impl Events for ReplayEvents {
+    fn new() -> io::Result<Self> {
+        Ok(Self::default())
+    }
+
+    fn read_with_deadline(&mut self, deadline: Option<Instant>) -> io::Result<Option<Event>> {
+        let Some(&(time, _)) = self.events.front() else {
+            return Ok(None);
+        };
+
+        if self.timing {
+            let due = *self.start.get_or_insert_with(Instant::now) + time;
+            let until = deadline.map_or(due, |deadline| deadline.min(due));
+
+            thread::sleep(until.saturating_duration_since(Instant::now()));
+            if until < due {
+                return Ok(None);
+            }
+        }
+
+        Ok(self.events.pop_front().map(|(_, event)| event))
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+
+    json.push('"');
+    json
+}
+
+/// Parse an event line (`[time, "code", "data"]`).
+fn parse_event(line: &str) -> Option<(Duration, String, String)> {
+    let line = line.strip_prefix('[')?.strip_suffix(']')?;
+
+    let (time, rest) = line.split_once(',')?;
+    let time = Duration::try_from_secs_f64(time.trim().parse().ok()?).ok()?;
+
+    let (code, rest) = parse_json_string(rest.trim_start())?;
+    let rest = rest.trim_start().strip_prefix(',')?;
+    let (data, rest) = parse_json_string(rest.trim_start())?;
+
+    rest.trim().is_empty().then_some((time, code, data))
+}
+
+/// Parse a JSON string from the start of `s`, returning it and the rest of `s`.
+fn parse_json_string(s: &str) -> Option<(String, &str)> {
+    let mut chars = s.strip_prefix('"')?.char_indices();
+    let mut string = String::new();
+
+    let hex = |chars: &mut std::str::CharIndices| -> Option<u32> {
+        let digits: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+        (digits.len() == 4)
+            .then(|| u32::from_str_radix(&digits, 16).ok())
+            .flatten()
+    };
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((string, &s[i + 2..])),
+            '\\' => {
+                let c = match chars.next()?.1 {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'b' => '\x08',
+                    'f' => '\x0c',
+                    'u' => {
+                        let mut code = hex(&mut chars)?;
+
+                        // Characters outside the BMP are split into surrogates.
+                        if (0xd800..0xdc00).contains(&code) {
+                            let (_, '\\') = chars.next()? else {
+                                return None;
+                            };
+                            let (_, 'u') = chars.next()? else { return None };
+                            let low = hex(&mut chars)?;
+                            if !(0xdc00..0xe000).contains(&low) {
+                                return None;
+                            }
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                        }
+
+                        char::from_u32(code)?
+                    }
+                    c => c,
+                };
+                string.push(c);
+            }
+            c => string.push(c),
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::fs::File;
+    use std::io::{self, Write};
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use std::{mem, ptr};
+
+    use super::{json_string, parse_event, parse_json_string, Recorder, ReplayEvents};
+    use crate::platform::ansi::AnsiWriter;
+    use crate::platform::ansi_event::AnsiEvents;
+    use crate::platform::event::{Event, Events, KeyCode, KeyEvent};
+    use crate::platform::headless::TestTerminal;
+    use crate::platform::input::RESIZE;
+    use crate::platform::{Terminal, Writer};
+    use crate::prelude::*;
+    use crate::vec2::Vec2;
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        fn lines(&self) -> Vec<String> {
+            let text = String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+            text.lines().map(str::to_owned).collect()
+        }
+    }
+
+    /// Open a pseudo-terminal in raw mode, returning the master and slave ends.
+    fn pty(size: Vec2) -> (File, File) {
+        let (mut master, mut slave) = (0, 0);
+        let winsize = libc::winsize {
+            ws_row: size.y,
+            ws_col: size.x,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+
+        unsafe {
+            let mut termios: libc::termios = mem::zeroed();
+            libc::cfmakeraw(&mut termios);
+
+            c_result!(libc::openpty(
+                &mut master,
+                &mut slave,
+                ptr::null_mut(),
+                &termios,
+                &winsize,
+            ))
+            .unwrap();
+
+            (File::from_raw_fd(master), File::from_raw_fd(slave))
+        }
+    }
+
+    #[test]
+    fn json_strings() {
+        let s = "a \"quoted\" \\ line\r\n\x1b[1m日本 😀\x7f";
+        let json = json_string(s);
+        assert_eq!(
+            json,
+            "\"a \\\"quoted\\\" \\\\ line\\r\\n\\u001b[1m日本 😀\x7f\""
+        );
+        assert_eq!(parse_json_string(&json), Some((s.to_owned(), "")));
+
+        assert_eq!(
+            parse_json_string(r#""😀é\/", "x""#),
+            Some(("😀é/".to_owned(), r#", "x""#))
+        );
+        assert_eq!(parse_json_string(r#""unterminated"#), None);
+
+        // Surrogate pairs, which have to be high then low.
+        assert_eq!(
+            parse_json_string(r#""\ud83d\ude00""#),
+            Some(("😀".to_owned(), ""))
+        );
+        assert_eq!(parse_json_string(r#""\ud83d\ue000""#), None);
+        assert_eq!(parse_json_string(r#""\ud83d\ud83d""#), None);
+
+        assert_eq!(
+            parse_event(r#"[1.5, "o", "hi"]"#),
+            Some((Duration::from_millis(1500), "o".to_owned(), "hi".to_owned()))
+        );
+        assert_eq!(parse_event(r#"[1.5, "o", "hi", "extra"]"#), None);
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let buf = SharedBuf::default();
+        let recorder = Recorder::new(buf.clone(), [80, 24]).unwrap();
+
+        recorder.input(b"h\x1b[A").unwrap();
+        recorder.output("\x1b[Hh").unwrap();
+        recorder.resize([100, 30].into()).unwrap();
+        recorder.input(b"\x1b").unwrap();
+
+        let recording = buf.0.lock().unwrap().clone();
+        let text = String::from_utf8(recording.clone()).unwrap();
+        assert!(text.starts_with(r#"{"version": 2, "width": 80, "height": 24, "#));
+        assert!(text.contains(r#", "o", "\u001b[Hh"]"#));
+
+        let mut replay = ReplayEvents::from_reader(recording.as_slice()).unwrap();
+        let mut events = vec![];
+        while let Some(event) = replay.read_with_deadline(None).unwrap() {
+            events.push(event);
+        }
+
+        assert_eq!(
+            events,
+            [
+                Event::Key(KeyEvent::key(KeyCode::Char('h'))),
+                Event::Key(KeyEvent::key(KeyCode::Up)),
+                Event::Resize([100, 30].into()),
+                Event::Key(KeyEvent::key(KeyCode::Escape)),
+            ]
+        );
+
+        assert!(ReplayEvents::from_reader(&b"[1.0, \"i\"]\n"[..]).is_err());
+    }
+
+    #[test]
+    fn split_input() {
+        let buf = SharedBuf::default();
+        let recorder = Recorder::new(buf.clone(), [80, 24]).unwrap();
+
+        // A character split between two reads.
+        let bytes = "a日".as_bytes();
+        recorder.input(&bytes[..2]).unwrap();
+        recorder.input(&bytes[2..]).unwrap();
+
+        let lines = buf.lines();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].ends_with(r#", "i", "a"]"#));
+        assert!(lines[2].ends_with(r#", "i", "日"]"#));
+
+        let mut replay = ReplayEvents::from_reader(lines.join("\n").as_bytes()).unwrap();
+        let mut events = vec![];
+        while let Some(event) = replay.read_with_deadline(None).unwrap() {
+            events.push(event);
+        }
+        assert_eq!(
+            events,
+            [
+                Event::Key(KeyEvent::key(KeyCode::Char('a'))),
+                Event::Key(KeyEvent::key(KeyCode::Char('日'))),
+            ]
+        );
+    }
+
+    #[test]
+    fn resize_after_escape() {
+        let recording = concat!(
+            r#"[0.5, "i", "\u001b"]"#,
+            "\n",
+            r#"[0.52, "r", "100x30"]"#,
+            "\n",
+        );
+        let mut replay = ReplayEvents::from_reader(recording.as_bytes()).unwrap();
+
+        assert_eq!(
+            replay.read_with_deadline(None).unwrap(),
+            Some(Event::Key(KeyEvent::key(KeyCode::Escape)))
+        );
+        assert_eq!(
+            replay.read_with_deadline(None).unwrap(),
+            Some(Event::Resize([100, 30].into()))
+        );
+    }
+
+    #[test]
+    fn record_writer() {
+        let buf = SharedBuf::default();
+        let mut w = AnsiWriter::new(vec![]).with_synchronized_output(true);
+        w.set_recorder(Some(Recorder::new(buf.clone(), [80, 24]).unwrap()));
+
+        w.set_cursor_home();
+        w.flush().unwrap();
+        w.flush().unwrap();
+
+        // Empty flushes aren't recorded.
+        let lines = buf.lines();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].ends_with(r#", "o", "\u001b[?2026h\u001b[H\u001b[?2026l"]"#));
+    }
+
+    #[test]
+    fn record_events() {
+        let (mut master, slave) = pty([80, 24].into());
+        let mut events = AnsiEvents::with_input(slave).unwrap();
+
+        let buf = SharedBuf::default();
+        events.set_recorder(Some(Recorder::new(buf.clone(), [80, 24]).unwrap()));
+
+        let deadline = Some(Instant::now() + Duration::from_secs(5));
+
+        master.write_all(b"hi").unwrap();
+        assert_eq!(
+            events.read_with_deadline(deadline).unwrap(),
+            Some(Event::Key(KeyEvent::key(KeyCode::Char('h'))))
+        );
+
+        // Resize the terminal, and poke the pipe like the `SIGWINCH` handler would.
+        let winsize = libc::winsize {
+            ws_row: 30,
+            ws_col: 100,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        c_result!(unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &winsize) }).unwrap();
+        c_result!(unsafe {
+            libc::write(
+                events.input_waker().as_raw_fd(),
+                [RESIZE].as_ptr().cast(),
+                1,
+            )
+        })
+        .unwrap();
+
+        assert_eq!(
+            events.read_with_deadline(deadline).unwrap(),
+            Some(Event::Key(KeyEvent::key(KeyCode::Char('i'))))
+        );
+        assert_eq!(
+            events.read_with_deadline(deadline).unwrap(),
+            Some(Event::Resize([100, 30].into()))
+        );
+
+        let lines = buf.lines();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].ends_with(r#", "i", "hi"]"#));
+        assert!(lines[2].ends_with(r#", "r", "100x30"]"#));
+    }
+
+    /// Keeps what's entered into its text field.
+    struct Prompt(Rc<RefCell<Vec<String>>>);
+
+    impl Component for Prompt {
+        type Message = String;
+        type View = TextField<String>;
+
+        fn on_message(&mut self, _ctx: &mut Context<String>, msg: &String) -> Handled {
+            self.0.borrow_mut().push(msg.clone());
+            Handled::Yes
+        }
+
+        fn build(&self) -> Self::View {
+            TextField::new().on_enter(|s| s)
+        }
+    }
+
+    #[test]
+    fn replay_app() {
+        let recording = concat!(
+            r#"{"version": 2, "width": 20, "height": 4}"#,
+            "\n",
+            r#"[0.5, "i", "hi"]"#,
+            "\n",
+            r#"[0.6, "o", "\u001b[Hhi"]"#,
+            "\n",
+            r#"[0.7, "i", "\r"]"#,
+            "\n",
+        );
+        let events = ReplayEvents::from_reader(recording.as_bytes()).unwrap();
+
+        let entered = Rc::new(RefCell::new(vec![]));
+        let mut app = App::new_with_terminal(
+            Prompt(entered.clone()),
+            TestTerminal::with_events([20, 4], events),
+        )
+        .unwrap();
+
+        assert!(app.run_frame().unwrap());
+        assert_eq!(*entered.borrow(), ["hi"]);
+        assert!(app.terminal_mut().events().is_empty());
+    }
+}
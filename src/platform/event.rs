@@ -1,17 +1,105 @@
 use std::io;
+#[cfg(feature = "async")]
+use std::pin::Pin;
+use std::sync::Arc;
+#[cfg(feature = "async")]
+use std::task::{self, Poll};
 use std::time::Instant;
 
 use bitflags::bitflags;
 
+use crate::vec2::Vec2;
+
 pub trait Events: Sized {
     fn new() -> io::Result<Self>;
-    fn read_with_deadline(&mut self, deadline: Instant) -> io::Result<Option<Event>>;
+
+    /// Wait for the next event, or `None` once the deadline has passed. With no
+    /// deadline, this waits until there's an event or the reader is woken.
+    fn read_with_deadline(&mut self, deadline: Option<Instant>) -> io::Result<Option<Event>>;
+
+    /// Something that can make a read return `None` early, from any thread.
+    ///
+    /// By default, reads can't be woken, so messages sent from other threads wait
+    /// for the next event. That's fine for events that never block.
+    fn waker(&self) -> EventWaker {
+        EventWaker::noop()
+    }
+}
+
+/// Interrupts a read of [`Events`], so that the reader can get on with
+/// something else.
+#[derive(Clone)]
+pub struct EventWaker(Arc<dyn Fn() + Send + Sync>);
+
+impl EventWaker {
+    pub fn new(wake: impl Fn() + Send + Sync + 'static) -> Self {
+        Self(Arc::new(wake))
+    }
+
+    /// A waker for events that never block, so never need waking.
+    pub fn noop() -> Self {
+        Self::new(|| {})
+    }
+
+    pub fn wake(&self) {
+        (self.0)()
+    }
+}
+
+/// Events that can be waited for without blocking, for use with an async
+/// runtime.
+#[cfg(feature = "async")]
+pub trait AsyncEvents: Events {
+    /// Poll for the next event, or `None` once the deadline has passed.
+    ///
+    /// If there isn't one yet, the task is woken when there might be, or at the
+    /// deadline.
+    fn poll_event(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        deadline: Option<Instant>,
+    ) -> Poll<io::Result<Option<Event>>>;
+
+    /// The events as a [`Stream`](futures::Stream), for use with `select!` and
+    /// the like.
+    fn stream(&mut self) -> EventStream<'_, Self> {
+        EventStream { events: self }
+    }
+}
+
+/// A stream of events, created by [`AsyncEvents::stream`].
+///
+/// The stream only ends if the event source runs out, which a terminal never
+/// does.
+#[cfg(feature = "async")]
+pub struct EventStream<'a, E> {
+    events: &'a mut E,
+}
+
+#[cfg(feature = "async")]
+impl<E: AsyncEvents> futures::Stream for EventStream<'_, E> {
+    type Item = io::Result<Event>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut()
+            .events
+            .poll_event(cx, None)
+            .map(Result::transpose)
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Event {
     Key(KeyEvent),
+    Mouse(MouseEvent),
     String(String),
+
+    /// Text pasted into the terminal, delivered in one piece.
+    Paste(String),
+
+    /// The terminal was resized to the given size.
+    Resize(Vec2),
+
     Unknown,
 }
 
@@ -75,3 +163,44 @@ bitflags! {
         const META  = 0b1000;
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MouseEvent {
+    pub kind: MouseEventKind,
+
+    /// The button involved, if known.
+    ///
+    /// This is always `None` for scroll events, and for release events reported
+    /// using the legacy X10 encoding, which doesn't say which button was
+    /// released.
+    pub button: Option<MouseButton>,
+
+    pub modifiers: Modifiers,
+
+    /// The cell the event occurred in, starting from `[0, 0]` in the top left.
+    pub pos: Vec2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseEventKind {
+    Press,
+    Release,
+    Drag,
+    Move,
+
+    ScrollUp,
+    ScrollDown,
+    ScrollLeft,
+    ScrollRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+
+    /// Usually the side button nearest the back of the mouse.
+    Back,
+    Forward,
+}
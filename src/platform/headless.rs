@@ -0,0 +1,492 @@
+//! A terminal that doesn't need a TTY, for testing.
+
+use std::collections::VecDeque;
+use std::io;
+use std::ops::Range;
+#[cfg(feature = "async")]
+use std::task::{self, Poll};
+use std::time::Instant;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+#[cfg(feature = "async")]
+use super::event::AsyncEvents;
+use super::event::{Event, Events, KeyCode, KeyEvent};
+use super::{Terminal, Writer};
+use crate::buffer::{grapheme_width, Buffer, Cell};
+use crate::style::{Color, Style, Underline, Weight};
+use crate::vec2::Vec2;
+
+/// A terminal that renders into an in-memory [`Buffer`] and reads events from
+/// a scripted queue, or from elsewhere such as a
+/// [`ReplayEvents`](super::asciicast::ReplayEvents).
+pub struct TestTerminal<E: Events = TestEvents> {
+    writer: TestWriter,
+    events: E,
+    times_suspended: usize,
+}
+
+impl TestTerminal {
+    pub fn new(size: impl Into<Vec2>) -> Self {
+        Self::with_events(size, TestEvents::default())
+    }
+
+    /// Resize the terminal, sending a resize event like a real terminal would.
+    pub fn resize(&mut self, size: impl Into<Vec2>) {
+        let size = size.into();
+
+        self.writer.screen.resize_and_clear(size);
+        self.events.push(Event::Resize(size));
+    }
+}
+
+impl<E: Events> TestTerminal<E> {
+    pub fn with_events(size: impl Into<Vec2>, events: E) -> Self {
+        Self {
+            writer: TestWriter::new(size),
+            events,
+            times_suspended: 0,
+        }
+    }
+
+    /// How many times the app has suspended itself.
+    pub fn times_suspended(&self) -> usize {
+        self.times_suspended
+    }
+}
+
+impl<E: Events> Terminal for TestTerminal<E> {
+    type Writer = TestWriter;
+    type Events = E;
+
+    fn init() -> io::Result<Self> {
+        Ok(Self::with_events([80, 24], E::new()?))
+    }
+
+    fn size(&self) -> io::Result<Vec2> {
+        Ok(self.writer.screen.size())
+    }
+
+    fn writer(&mut self) -> &mut Self::Writer {
+        &mut self.writer
+    }
+
+    fn events(&mut self) -> &mut Self::Events {
+        &mut self.events
+    }
+
+    fn suspend(&mut self) -> io::Result<()> {
+        self.times_suspended += 1;
+        Ok(())
+    }
+}
+
+/// A writer that interprets what it's told to draw, rather than encoding it.
+pub struct TestWriter {
+    screen: Buffer,
+    style: Style,
+
+    cursor_pos: Vec2,
+    cursor_vis: bool,
+
+    alt_screen: bool,
+    mouse_capture: bool,
+    bracketed_paste: bool,
+}
+
+impl TestWriter {
+    pub fn new(size: impl Into<Vec2>) -> Self {
+        Self {
+            screen: Buffer::new(size),
+            style: Style::default(),
+
+            cursor_pos: Vec2::default(),
+            cursor_vis: true,
+
+            alt_screen: false,
+            mouse_capture: false,
+            bracketed_paste: false,
+        }
+    }
+
+    /// The current contents of the screen.
+    pub fn screen(&self) -> &Buffer {
+        &self.screen
+    }
+
+    /// The text on the given line of the screen, without trailing whitespace.
+    pub fn line(&self, y: u16) -> String {
+        let mut line: String = (0..self.screen.size().x)
+            .map(|x| match self.screen.get([x, y]) {
+                Some(Some(cell)) => cell.symbol(),
+                _ => " ",
+            })
+            .collect();
+
+        line.truncate(line.trim_end().len());
+        line
+    }
+
+    /// The position of the cursor, if it's visible.
+    pub fn cursor(&self) -> Option<Vec2> {
+        self.cursor_vis.then_some(self.cursor_pos)
+    }
+
+    pub fn alt_screen(&self) -> bool {
+        self.alt_screen
+    }
+
+    pub fn mouse_capture(&self) -> bool {
+        self.mouse_capture
+    }
+
+    pub fn bracketed_paste(&self) -> bool {
+        self.bracketed_paste
+    }
+
+    /// What the terminal fills erased cells with: a blank with the current
+    /// background color.
+    fn blank(&self) -> Option<Cell> {
+        Some(Cell::new(
+            ' ',
+            Style {
+                bg: self.style.bg,
+                ..Style::default()
+            },
+        ))
+    }
+
+    fn erase(&mut self, n: u16) {
+        let blank = self.blank();
+        let mut view = self.screen.view(false);
+        for x in self.cursor_pos.x..self.cursor_pos.x.saturating_add(n) {
+            if let Some(cell) = view.get_mut([x, self.cursor_pos.y]) {
+                *cell = blank;
+            }
+        }
+    }
+}
+
+impl Writer for TestWriter {
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn buffered_len(&self) -> usize {
+        0
+    }
+
+    fn clear_all(&mut self) {
+        let size = self.screen.size();
+        self.screen.resize_and_clear(size);
+    }
+
+    fn set_cursor_home(&mut self) {
+        self.cursor_pos = Vec2::default();
+    }
+
+    fn next_line(&mut self) {
+        self.cursor_pos = Vec2::new(0, self.cursor_pos.y.saturating_add(1));
+    }
+
+    fn set_cursor_pos(&mut self, pos: impl Into<Vec2>) {
+        self.cursor_pos = pos.into();
+    }
+
+    fn set_cursor_vis(&mut self, vis: bool) {
+        self.cursor_vis = vis;
+    }
+
+    fn clear_line_right(&mut self) {
+        self.erase(self.screen.size().x);
+    }
+
+    fn erase_chars(&mut self, n: u16) {
+        self.erase(n);
+    }
+
+    fn repeat_str(&mut self, s: &str, n: u16) {
+        for _ in 0..n {
+            self.write_str(s);
+        }
+    }
+
+    fn scroll_rows(&mut self, rows: Range<u16>, amount: i16) {
+        let width = self.screen.size().x;
+        let blank = self.blank();
+        let mut view = self.screen.view(false);
+
+        let src_row = |y: u16| y.checked_add_signed(amount).filter(|y| rows.contains(y));
+        let ys: Vec<u16> = match amount {
+            1.. => rows.clone().collect(),
+            _ => rows.clone().rev().collect(),
+        };
+
+        for y in ys {
+            for x in 0..width {
+                view[[x, y]] = match src_row(y) {
+                    Some(src_y) => view[[x, src_y]],
+                    None => blank,
+                };
+            }
+        }
+
+        self.cursor_pos = Vec2::default();
+    }
+
+    fn set_alt_screen(&mut self, enabled: bool) {
+        self.alt_screen = enabled;
+    }
+
+    fn set_mouse_capture(&mut self, capture: bool) {
+        self.mouse_capture = capture;
+    }
+
+    fn set_bracketed_paste(&mut self, enabled: bool) {
+        self.bracketed_paste = enabled;
+    }
+
+    fn set_fg_color(&mut self, c: Color) {
+        self.style.fg = c;
+    }
+
+    fn set_bg_color(&mut self, c: Color) {
+        self.style.bg = c;
+    }
+
+    fn set_weight(&mut self, weight: Weight) {
+        self.style.weight = weight;
+    }
+
+    fn set_italic(&mut self, italic: bool) {
+        self.style.italic = italic;
+    }
+
+    fn set_strikethrough(&mut self, strikethrough: bool) {
+        self.style.strikethrough = strikethrough;
+    }
+
+    fn set_reverse(&mut self, reverse: bool) {
+        self.style.reverse = reverse;
+    }
+
+    fn set_blink(&mut self, blink: bool) {
+        self.style.blink = blink;
+    }
+
+    fn set_hidden(&mut self, hidden: bool) {
+        self.style.hidden = hidden;
+    }
+
+    fn set_underline(&mut self, underline: Underline) {
+        self.style.underline = underline;
+    }
+
+    fn set_underline_color(&mut self, c: Color) {
+        self.style.underline_color = c;
+    }
+
+    fn write_char(&mut self, c: char) {
+        self.write_str(c.encode_utf8(&mut [0; 4]));
+    }
+
+    fn write_str(&mut self, s: &str) {
+        for grapheme in s.graphemes(true) {
+            let width = grapheme_width(grapheme);
+            if width == 0 {
+                continue;
+            }
+
+            // Wrap like a terminal would.
+            if self.cursor_pos.x + width > self.screen.size().x {
+                self.next_line();
+            }
+
+            let new_cell = Cell::from_grapheme(grapheme, self.style);
+
+            let mut view = self.screen.view(false);
+            if let Some(cell) = view.get_mut(self.cursor_pos) {
+                *cell = Some(new_cell);
+            }
+            if width == 2 {
+                if let Some(cell) = view.get_mut([self.cursor_pos.x + 1, self.cursor_pos.y]) {
+                    *cell = Some(Cell::continuation(self.style));
+                }
+            }
+
+            self.cursor_pos.x = self.cursor_pos.x.saturating_add(width);
+        }
+    }
+
+    fn write_style(&mut self, style: Style) {
+        self.style = style;
+    }
+}
+
+/// Events fed from a queue.
+///
+/// Reading never blocks: once the queue is empty, reads return `None` straight
+/// away, as if the deadline had passed.
+#[derive(Default)]
+pub struct TestEvents {
+    queue: VecDeque<Event>,
+}
+
+impl TestEvents {
+    pub fn push(&mut self, event: Event) {
+        self.queue.push_back(event);
+    }
+
+    pub fn push_key(&mut self, key_event: KeyEvent) {
+        self.push(Event::Key(key_event));
+    }
+
+    /// Push a key event for each character of a string, as if it were typed.
+    pub fn push_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.push_key(KeyEvent::key(KeyCode::Char(c)));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+impl Events for TestEvents {
+    fn new() -> io::Result<Self> {
+        Ok(Self::default())
+    }
+
+    fn read_with_deadline(&mut self, _deadline: Option<Instant>) -> io::Result<Option<Event>> {
+        Ok(self.queue.pop_front())
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncEvents for TestEvents {
+    fn poll_event(
+        &mut self,
+        _cx: &mut task::Context<'_>,
+        _deadline: Option<Instant>,
+    ) -> Poll<io::Result<Option<Event>>> {
+        Poll::Ready(Ok(self.queue.pop_front()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+
+    enum Message {
+        Entered(String),
+    }
+
+    #[derive(Default)]
+    struct Prompt {
+        entered: Vec<String>,
+    }
+
+    impl Component for Prompt {
+        type Message = Message;
+        type View = Stack<Message>;
+
+        fn on_message(&mut self, ctx: &mut Context<Message>, msg: &Message) -> Handled {
+            match msg {
+                Message::Entered(s) if s == "quit" => ctx.quit(),
+                Message::Entered(s) => {
+                    self.entered.push(s.clone());
+                    ctx.rebuild_view();
+                }
+            }
+            Handled::Yes
+        }
+
+        fn build(&self) -> Self::View {
+            let mut stack = Stack::new();
+
+            stack.push(
+                Label::new(format!("entered: {}", self.entered.join(","))),
+                SizeConstraint::fixed(1),
+            );
+            stack.push(
+                TextField::new().on_enter(Message::Entered),
+                SizeConstraint::fixed(1),
+            );
+            stack.set_focus(Some(1));
+
+            stack
+        }
+    }
+
+    fn app() -> App<Prompt, TestTerminal> {
+        App::new_with_terminal(Prompt::default(), TestTerminal::new([20, 4])).unwrap()
+    }
+
+    fn writer(app: &mut App<Prompt, TestTerminal>) -> &mut TestWriter {
+        app.terminal_mut().writer()
+    }
+
+    #[test]
+    fn type_and_enter() {
+        let mut app = app();
+
+        assert!(app.run_frame().unwrap());
+        assert_eq!(writer(&mut app).line(0), "entered:");
+        assert_eq!(writer(&mut app).cursor(), Some([0, 1].into()));
+
+        app.terminal_mut().events().push_str("hello");
+        assert!(app.run_frame().unwrap());
+        assert_eq!(writer(&mut app).line(1), "hello");
+        assert_eq!(writer(&mut app).cursor(), Some([5, 1].into()));
+
+        let events = app.terminal_mut().events();
+        events.push_key(KeyEvent::key(KeyCode::Return));
+        events.push_str("world");
+        events.push_key(KeyEvent::key(KeyCode::Return));
+
+        assert!(app.run_frame().unwrap());
+        assert_eq!(writer(&mut app).line(0), "entered: hello,world");
+        assert_eq!(writer(&mut app).line(1), "");
+
+        app.terminal_mut().events().push_str("quit");
+        app.terminal_mut()
+            .events()
+            .push_key(KeyEvent::key(KeyCode::Return));
+        assert!(!app.run_frame().unwrap());
+    }
+
+    #[test]
+    fn resize() {
+        let mut app = app();
+        assert!(app.run_frame().unwrap());
+
+        app.terminal_mut().resize([6, 2]);
+        assert!(app.run_frame().unwrap());
+
+        assert_eq!(writer(&mut app).screen().size(), [6, 2].into());
+        assert_eq!(writer(&mut app).line(0), "entere");
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn run_async() {
+        let mut app = app();
+
+        let events = app.terminal_mut().events();
+        events.push_str("hi");
+        events.push_key(KeyEvent::key(KeyCode::Return));
+
+        futures::executor::block_on(async {
+            assert!(app.run_frame_async().await.unwrap());
+            assert_eq!(writer(&mut app).line(0), "entered: hi");
+
+            app.terminal_mut().events().push_str("quit");
+            app.terminal_mut()
+                .events()
+                .push_key(KeyEvent::key(KeyCode::Return));
+            app.run_async().await.unwrap();
+        });
+    }
+}
@@ -1,8 +1,20 @@
-use std::io::{self, Read};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::sync::Arc;
 use std::time::Instant;
-use std::{fmt, thread};
 
-use crossbeam_channel::{Receiver, RecvTimeoutError};
+use libc::c_int;
+
+use super::event::Event;
+use super::linux::get_size;
+
+/// Written to the wake pipe to interrupt a read.
+const WAKE: u8 = 0;
+
+/// Written to the wake pipe when the terminal has been resized.
+pub(crate) const RESIZE: u8 = 1;
 
 #[derive(Default, Clone)]
 pub(crate) struct Bytes {
@@ -14,14 +26,6 @@ impl Bytes {
     pub fn as_slice(&self) -> &[u8] {
         &self.buf[..self.len]
     }
-
-    pub fn len(&self) -> usize {
-        self.len
-    }
-
-    pub fn is_empty(&self) -> bool {
-        self.len == 0
-    }
 }
 
 impl fmt::Debug for Bytes {
@@ -31,50 +35,210 @@ impl fmt::Debug for Bytes {
     }
 }
 
-pub(crate) struct PollingStdin {
-    recv: Receiver<io::Result<Bytes>>,
+/// Something received while polling for input.
+pub(crate) enum Input {
+    /// Bytes read from the terminal.
+    Bytes(Bytes),
+
+    /// An event that didn't come from the terminal's input, such as a resize.
+    Event(Event),
+
+    /// A [`Waker`] interrupted the read.
+    Wake,
+}
+
+/// Create a pipe, returning the read and write ends.
+///
+/// Both ends are non-blocking, so that a signal handler can never get stuck
+/// writing to it and the reader can drain it.
+pub(crate) fn pipe() -> io::Result<(File, File)> {
+    let mut fds = [0; 2];
+    c_result!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) })?;
+
+    let [read, write] = fds.map(|fd| unsafe { File::from_raw_fd(fd) });
+    Ok((read, write))
 }
 
-impl Default for PollingStdin {
-    fn default() -> Self {
-        Self::new()
+/// The write end of the pipe a [`PollInput`] waits on alongside the terminal.
+#[derive(Clone)]
+pub(crate) struct Waker {
+    pipe: Arc<File>,
+}
+
+impl Waker {
+    pub fn wake(&self) {
+        // If the pipe is full, there's already a wakeup pending.
+        let _ = (&*self.pipe).write(&[WAKE]);
+    }
+
+    /// Wake the poller with a resize, so that everything gets redrawn.
+    pub fn resize(&self) {
+        let _ = (&*self.pipe).write(&[RESIZE]);
     }
 }
 
-impl PollingStdin {
-    pub fn new() -> Self {
-        let (send, recv) = crossbeam_channel::bounded(8);
+impl AsRawFd for Waker {
+    fn as_raw_fd(&self) -> RawFd {
+        self.pipe.as_raw_fd()
+    }
+}
 
-        let mut stdin = io::stdin();
-        thread::spawn(move || loop {
-            let mut bytes = Bytes::default();
+/// Reads the terminal with `poll`, so that reads can time out without needing
+/// a thread to do the reading.
+pub(crate) struct PollInput {
+    input: File,
 
-            match stdin.read(&mut bytes.buf) {
-                // Some bytes were written, so send them to the main thread.
-                Ok(len) => {
-                    bytes.len = len;
-                    send.send(Ok(bytes)).unwrap();
+    wake_read: File,
+    waker: Waker,
+
+    /// Whether a wakeup arrived along with a resize, and is yet to be returned.
+    woken: bool,
+}
+
+impl PollInput {
+    pub fn new(input: File) -> io::Result<Self> {
+        let (wake_read, wake_write) = pipe()?;
+
+        Ok(Self {
+            input,
+
+            wake_read,
+            waker: Waker {
+                pipe: Arc::new(wake_write),
+            },
+
+            woken: false,
+        })
+    }
+
+    pub fn waker(&self) -> Waker {
+        self.waker.clone()
+    }
+
+    /// The file descriptors that are polled for input.
+    #[cfg(feature = "async")]
+    pub fn fds(&self) -> [RawFd; 2] {
+        [self.input.as_raw_fd(), self.wake_read.as_raw_fd()]
+    }
+
+    /// Wait for input until the deadline, returning `None` if there wasn't any.
+    pub fn read_with_deadline(&mut self, deadline: Option<Instant>) -> io::Result<Option<Input>> {
+        if std::mem::take(&mut self.woken) {
+            return Ok(Some(Input::Wake));
+        }
+
+        loop {
+            let mut fds =
+                [self.input.as_raw_fd(), self.wake_read.as_raw_fd()].map(|fd| libc::pollfd {
+                    fd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                });
+
+            // Round up, so that we don't wake up just before the deadline and spin.
+            let timeout_ms = match deadline {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    timeout.as_micros().div_ceil(1000).min(c_int::MAX as u128) as c_int
                 }
+                None => -1,
+            };
 
-                // Interrupted - continue reading.
-                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            match c_result!(unsafe { libc::poll(fds.as_mut_ptr(), 2, timeout_ms) }) {
+                Ok(0) => return Ok(None),
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+
+            if fds[1].revents != 0 {
+                let (resized, woken) = self.drain_wake_pipe()?;
 
-                // Eww, an error.
-                Err(err) => send.send(Err(err)).unwrap(),
+                if resized {
+                    self.woken = woken;
+
+                    let size = unsafe { get_size(self.input.as_raw_fd())? };
+                    return Ok(Some(Input::Event(Event::Resize(size))));
+                }
+                if woken {
+                    return Ok(Some(Input::Wake));
+                }
             }
-        });
 
-        Self { recv }
+            if fds[0].revents != 0 {
+                let mut bytes = Bytes::default();
+
+                match self.input.read(&mut bytes.buf) {
+                    Ok(0) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "the terminal was closed",
+                        ))
+                    }
+                    Ok(len) => {
+                        bytes.len = len;
+                        return Ok(Some(Input::Bytes(bytes)));
+                    }
+                    Err(err)
+                        if matches!(
+                            err.kind(),
+                            io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+                        ) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        }
     }
 
-    pub fn read_with_deadline(&self, deadline: Instant) -> io::Result<Option<Bytes>> {
-        match self.recv.recv_deadline(deadline) {
-            Ok(bytes) => bytes.map(Some),
-            Err(RecvTimeoutError::Timeout) => Ok(None),
-            Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(
-                io::ErrorKind::Other,
-                "input thread disconnected",
-            )),
+    /// Empty the wake pipe, returning whether there was a resize and whether
+    /// there was a wakeup.
+    fn drain_wake_pipe(&mut self) -> io::Result<(bool, bool)> {
+        let (mut resized, mut woken) = (false, false);
+        let mut buf = [0; 32];
+
+        loop {
+            match self.wake_read.read(&mut buf) {
+                Ok(0) => return Ok((resized, woken)),
+                Ok(len) => {
+                    resized |= buf[..len].contains(&RESIZE);
+                    woken |= buf[..len].contains(&WAKE);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok((resized, woken)),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn poll_input() {
+        let (read, mut write) = pipe().unwrap();
+        let mut input = PollInput::new(read).unwrap();
+
+        let soon = || Instant::now() + Duration::from_millis(10);
+        assert!(input.read_with_deadline(Some(soon())).unwrap().is_none());
+
+        write.write_all(b"abc").unwrap();
+        let Some(Input::Bytes(bytes)) = input.read_with_deadline(Some(soon())).unwrap() else {
+            panic!("expected bytes");
+        };
+        assert_eq!(bytes.as_slice(), b"abc");
+
+        input.waker().wake();
+        let far = Instant::now() + Duration::from_secs(60);
+        assert!(matches!(
+            input.read_with_deadline(Some(far)),
+            Ok(Some(Input::Wake))
+        ));
+
+        drop(write);
+        assert!(input.read_with_deadline(Some(soon())).is_err());
+    }
+}
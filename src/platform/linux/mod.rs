@@ -1,56 +1,318 @@
 mod raw_term;
+mod restore;
+mod signal;
 
-use std::io;
+use std::io::{self, Write as _};
+use std::thread;
+use std::time::Duration;
 
+pub(crate) use raw_term::get_size;
 use raw_term::RawTerm;
+use restore::RestoreGuard;
+use signal::ResizeSignal;
 
 use super::ansi::AnsiWriter;
 use super::ansi_event::AnsiEvents;
-use super::{Terminal, Writer};
+use super::asciicast::Recorder;
+use super::{Terminal, Viewport, Writer};
 use crate::style::Style;
 use crate::vec2::Vec2;
 
+/// How long to wait for the terminal to answer a query.
+const QUERY_TIMEOUT: Duration = Duration::from_millis(200);
+/// How long to wait for the device attributes, which every terminal reports
+/// eventually. Giving up early would leave the reply to be read as input.
+const DEVICE_ATTRIBUTES_TIMEOUT: Duration = Duration::from_secs(1);
+
 pub struct LinuxTerminal {
     raw_term: AnsiWriter<RawTerm>,
     events: AnsiEvents,
+    viewport: Viewport,
+    _resize_signal: ResizeSignal,
+    _restore_guard: RestoreGuard,
 }
 
-impl Terminal for LinuxTerminal {
-    type Writer = AnsiWriter<RawTerm>;
-    type Events = AnsiEvents;
+impl LinuxTerminal {
+    /// Set up the terminal to draw to the given part of the screen.
+    pub fn with_viewport(viewport: Viewport) -> io::Result<Self> {
+        let mut raw_term = RawTerm::new()?;
+        let restore_guard = RestoreGuard::new(
+            raw_term.fd(),
+            raw_term.termios_prev(),
+            viewport == Viewport::Fullscreen,
+        )?;
+
+        // These have to happen before we start reading events, as they read the
+        // terminal's replies from its input.
+        let origin = match viewport {
+            Viewport::Fullscreen => None,
+            Viewport::Inline(height) => Some(reserve_lines(&mut raw_term, height)?),
+        };
+
+        let synchronized_output = detect_synchronized_output(&mut raw_term)?;
+
+        // Anything that's arrived by now is a reply we stopped waiting for, or
+        // typed before the app was ready for it.
+        raw_term.discard_input()?;
+
+        let mut events = AnsiEvents::with_input(raw_term.try_clone_tty()?)?;
+        events.set_viewport(viewport, origin.unwrap_or(0));
+
+        let resize_signal = ResizeSignal::new(events.input_waker())?;
+
+        let mut raw_term = AnsiWriter::new(raw_term).with_synchronized_output(synchronized_output);
+        raw_term.set_origin(origin);
 
-    fn init() -> io::Result<Self> {
         let mut term = Self {
-            raw_term: AnsiWriter::new(RawTerm::new()?),
-            events: AnsiEvents::default(),
+            raw_term,
+            events,
+            viewport,
+            _resize_signal: resize_signal,
+            _restore_guard: restore_guard,
         };
 
-        term.writer().clear_all();
-        term.writer().flush()?;
+        term.enter()?;
 
         Ok(term)
     }
 
+    /// Record the session to an asciicast v2 file: everything that's drawn, and
+    /// the input that's read. Call this before the first frame, so that the
+    /// recording starts with a full redraw.
+    pub fn record(&mut self, out: impl io::Write + Send + 'static) -> io::Result<()> {
+        let recorder = Recorder::new(out, self.raw_term.inner().get_size()?)?;
+        self.raw_term.set_recorder(Some(recorder.clone()));
+        self.events.set_recorder(Some(recorder));
+        Ok(())
+    }
+
+    /// Switch on everything we need to draw and read input.
+    fn enter(&mut self) -> io::Result<()> {
+        if self.viewport == Viewport::Fullscreen {
+            self.writer().set_alt_screen(true);
+        }
+        self.writer().clear_all();
+        self.writer().set_mouse_capture(true);
+        self.writer().set_bracketed_paste(true);
+        self.writer().flush()
+    }
+
+    /// Take the terminal back after it's been restored for the shell.
+    fn resume(&mut self) -> io::Result<()> {
+        self.raw_term.inner_mut().set_raw(true)?;
+        restore::arm();
+
+        // Something will have been printed below the last frame, so start a new
+        // one.
+        if let Viewport::Inline(height) = self.viewport {
+            let origin = reserve_lines(self.raw_term.inner_mut(), height)?;
+            self.raw_term.set_origin(Some(origin));
+            self.events.set_viewport(self.viewport, origin);
+        }
+
+        self.enter()
+    }
+
+    /// If the panic hook restored the terminal but the panic was caught, set
+    /// it up again and have everything redrawn.
+    fn recover_from_panic(&mut self) -> io::Result<()> {
+        // While unwinding, we're about to be dropped anyway.
+        if thread::panicking() || !restore::take_panic_restored() {
+            return Ok(());
+        }
+
+        self.resume()?;
+        self.events.input_waker().resize();
+        Ok(())
+    }
+
+    /// Undo [`Self::enter`].
+    fn leave(&mut self) -> io::Result<()> {
+        self.writer().set_mouse_capture(false);
+        self.writer().set_bracketed_paste(false);
+        self.writer().set_cursor_vis(true);
+        self.writer().write_style(Style::default());
+
+        match self.viewport {
+            Viewport::Fullscreen => self.writer().set_alt_screen(false),
+
+            // Leave the last frame where it is, with the cursor on the line below.
+            Viewport::Inline(_) => {
+                let height = self.size().map_or(1, |size| size.y);
+                self.writer().set_cursor_pos([0, height.saturating_sub(1)]);
+                self.writer().next_line();
+            }
+        }
+
+        self.writer().flush()
+    }
+}
+
+/// Make room for an inline viewport of the given height at the cursor,
+/// returning the row it starts on.
+fn reserve_lines(raw_term: &mut RawTerm, height: u16) -> io::Result<u16> {
+    let term_height = raw_term.get_size()?.y;
+    let height = height.clamp(1, term_height.max(1));
+
+    // Move down and back up again, so that the terminal scrolls if there isn't
+    // enough room below the cursor.
+    let mut reserve = "\r".to_owned();
+    if height > 1 {
+        reserve.push_str(&"\n".repeat(height as usize - 1));
+        reserve.push_str(&format!("\x1b[{}A", height - 1));
+    }
+    raw_term.write_all(reserve.as_bytes())?;
+
+    let reply = raw_term.query("\x1b[6n", QUERY_TIMEOUT, |reply| reply.ends_with(b"R"))?;
+
+    // If the terminal didn't answer, the best guess is that we scrolled to the
+    // bottom of the screen.
+    Ok(reply
+        .as_deref()
+        .and_then(parse_cursor_pos)
+        .map_or(term_height - height, |pos| pos.y))
+}
+
+/// Ask the terminal whether it supports synchronized updates (DEC mode 2026).
+///
+/// Terminals that don't know about the mode might not answer at all, so we ask
+/// for the device attributes afterwards, which every terminal answers. If that
+/// comes back first, the mode isn't supported.
+fn detect_synchronized_output(raw_term: &mut RawTerm) -> io::Result<bool> {
+    let reply = raw_term.query(
+        "\x1b[?2026$p\x1b[c",
+        DEVICE_ATTRIBUTES_TIMEOUT,
+        has_device_attributes,
+    )?;
+
+    // 1 and 2 mean it's supported and currently set or reset, 3 that it's
+    // always set.
+    Ok(reply
+        .as_deref()
+        .and_then(|reply| parse_mode_report(reply, 2026))
+        .is_some_and(|value| matches!(value, 1..=3)))
+}
+
+/// Parse the value from a report of a DEC private mode (`CSI ? mode ; value $
+/// y`).
+fn parse_mode_report(reply: &[u8], mode: u16) -> Option<u8> {
+    let prefix = format!("\x1b[?{mode};");
+    let start = find(reply, prefix.as_bytes())? + prefix.len();
+
+    let reply = &reply[start..];
+    let end = find(reply, b"$y")?;
+    std::str::from_utf8(&reply[..end]).ok()?.parse().ok()
+}
+
+/// Whether there's a primary device attributes report (`CSI ? params c`).
+fn has_device_attributes(reply: &[u8]) -> bool {
+    reply.windows(3).enumerate().any(|(i, w)| {
+        w == b"\x1b[?"
+            && reply[i + 3..]
+                .iter()
+                .find(|b| !matches!(b, b'0'..=b'9' | b';'))
+                .is_some_and(|&b| b == b'c')
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Parse a cursor position report (`CSI row ; col R`), skipping anything that
+/// was typed before it.
+fn parse_cursor_pos(reply: &[u8]) -> Option<Vec2> {
+    let start = reply.windows(2).rposition(|w| w == b"\x1b[")?;
+    let reply = std::str::from_utf8(&reply[start + 2..]).ok()?;
+
+    let (row, col) = reply.strip_suffix('R')?.split_once(';')?;
+    let row: u16 = row.parse().ok()?;
+    let col: u16 = col.parse().ok()?;
+
+    Some(Vec2::new(col.checked_sub(1)?, row.checked_sub(1)?))
+}
+
+impl Terminal for LinuxTerminal {
+    type Writer = AnsiWriter<RawTerm>;
+    type Events = AnsiEvents;
+
+    fn init() -> io::Result<Self> {
+        Self::with_viewport(Viewport::Fullscreen)
+    }
+
     fn size(&self) -> io::Result<Vec2> {
-        self.raw_term.inner().get_size()
+        Ok(self.viewport.size(self.raw_term.inner().get_size()?))
     }
 
     fn writer(&mut self) -> &mut Self::Writer {
+        // The viewport moves if a resize would leave it off the screen.
+        if let Viewport::Inline(_) = self.viewport {
+            self.raw_term.set_origin(Some(self.events.origin()));
+        }
         &mut self.raw_term
     }
 
     fn events(&mut self) -> &mut Self::Events {
+        // If this fails, reading from the terminal will too.
+        let _ = self.recover_from_panic();
         &mut self.events
     }
+
+    fn suspend(&mut self) -> io::Result<()> {
+        // The shell gets the terminal back as it was, so there's nothing for the
+        // hooks to restore while we're stopped.
+        restore::disarm();
+        self.leave()?;
+        self.raw_term.inner_mut().set_raw(false)?;
+
+        // This doesn't return until we're continued.
+        c_result!(unsafe { libc::raise(libc::SIGTSTP) })?;
+
+        self.resume()
+    }
 }
 
 impl Drop for LinuxTerminal {
     fn drop(&mut self) {
-        self.writer().clear_all();
-        self.writer().set_cursor_home();
-        self.writer().set_cursor_vis(true);
-        self.writer().write_style(Style::default());
+        // If a panic hook already restored the terminal, don't write over the
+        // panic message.
+        if !restore::disarm() {
+            return;
+        }
+
+        let _ = self.leave();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{has_device_attributes, parse_cursor_pos, parse_mode_report};
+    use crate::vec2::Vec2;
+
+    #[test]
+    fn cursor_pos_report() {
+        assert_eq!(parse_cursor_pos(b"\x1b[12;3R"), Some(Vec2::new(2, 11)));
+        assert_eq!(
+            parse_cursor_pos(b"ab\x1b[A\x1b[1;1R"),
+            Some(Vec2::new(0, 0))
+        );
+        assert_eq!(parse_cursor_pos(b"\x1b[12R"), None);
+    }
+
+    #[test]
+    fn synchronized_output_reply() {
+        let supported = b"\x1b[?2026;2$y\x1b[?62;22c";
+        assert_eq!(parse_mode_report(supported, 2026), Some(2));
+        assert!(has_device_attributes(supported));
+
+        // Only the device attributes, from a terminal that ignored the query.
+        let unsupported = b"\x1b[?1;2c";
+        assert_eq!(parse_mode_report(unsupported, 2026), None);
+        assert!(has_device_attributes(unsupported));
 
-        let _ = self.writer().flush();
+        assert!(!has_device_attributes(b"\x1b[?2026;0$y"));
+        assert!(!has_device_attributes(b"\x1b[?62;2"));
     }
 }
@@ -1,24 +1,16 @@
-use std::fs::File;
-use std::mem::ManuallyDrop;
-use std::os::fd::{FromRawFd, RawFd};
+use std::fs::{File, OpenOptions};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use std::{io, mem};
 
-use libc::{termios as Termios, winsize as WinSize, STDIN_FILENO, STDOUT_FILENO};
+use libc::{c_int, termios as Termios, winsize as WinSize};
 
 use crate::vec2::Vec2;
 
 static RAW_TERM: AtomicBool = AtomicBool::new(false);
 
-macro_rules! c_result {
-    ($res:expr) => {{
-        match $res {
-            -1 => Err(io::Error::last_os_error()),
-            res => Ok(res),
-        }
-    }};
-}
-
 unsafe fn get_termios(fd: RawFd) -> io::Result<Termios> {
     unsafe {
         let mut termios: Termios = mem::zeroed();
@@ -32,14 +24,20 @@ unsafe fn set_termios(fd: RawFd, termios: &Termios) -> io::Result<()> {
     Ok(())
 }
 
-unsafe fn get_size(fd: RawFd) -> io::Result<Vec2> {
+pub(crate) unsafe fn get_size(fd: RawFd) -> io::Result<Vec2> {
     let mut size: WinSize = unsafe { mem::zeroed() };
     c_result!(unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) })?;
     Ok([size.ws_col, size.ws_row].into())
 }
 
+/// The controlling terminal, in raw mode.
+///
+/// This uses `/dev/tty` rather than stdin and stdout, so that they're left free
+/// for the app to read and write data, e.g. when used in a pipeline.
 pub struct RawTerm {
+    tty: File,
     termios_prev: Termios,
+    termios_raw: Termios,
 }
 
 impl RawTerm {
@@ -51,43 +49,128 @@ impl RawTerm {
             ));
         };
 
-        let mut termios = unsafe { get_termios(STDIN_FILENO)? };
+        Self::open().inspect_err(|_| RAW_TERM.store(false, Ordering::Relaxed))
+    }
+
+    fn open() -> io::Result<Self> {
+        let tty = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open("/dev/tty")?;
+
+        let mut termios = unsafe { get_termios(tty.as_raw_fd())? };
         let termios_prev = termios;
 
         unsafe { libc::cfmakeraw(&mut termios as *mut Termios) };
-        unsafe { set_termios(STDIN_FILENO, &termios)? };
+        unsafe { set_termios(tty.as_raw_fd(), &termios)? };
+
+        Ok(Self {
+            tty,
+            termios_prev,
+            termios_raw: termios,
+        })
+    }
+
+    /// Open the terminal again, for reading input.
+    pub fn try_clone_tty(&self) -> io::Result<File> {
+        self.tty.try_clone()
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.tty.as_raw_fd()
+    }
+
+    /// Switch between raw mode and the mode the terminal was in before.
+    pub fn set_raw(&mut self, raw: bool) -> io::Result<()> {
+        let termios = if raw {
+            &self.termios_raw
+        } else {
+            &self.termios_prev
+        };
+        unsafe { set_termios(self.fd(), termios) }
+    }
 
-        Ok(Self { termios_prev })
+    /// The terminal settings from before raw mode was entered.
+    pub fn termios_prev(&self) -> &Termios {
+        &self.termios_prev
     }
 
     pub fn get_size(&self) -> io::Result<Vec2> {
-        unsafe { get_size(STDIN_FILENO) }
+        unsafe { get_size(self.fd()) }
+    }
+
+    /// Throw away any input that hasn't been read yet.
+    pub fn discard_input(&mut self) -> io::Result<()> {
+        c_result!(unsafe { libc::tcflush(self.fd(), libc::TCIFLUSH) })?;
+        Ok(())
+    }
+
+    /// Send a query to the terminal and read the reply, which is complete once
+    /// `is_complete` returns true for everything read so far. Returns `None` if
+    /// the terminal doesn't reply within `timeout`.
+    ///
+    /// This reads the terminal directly, so it has to be done before anything
+    /// else starts reading it.
+    pub fn query(
+        &mut self,
+        query: &str,
+        timeout: Duration,
+        is_complete: impl Fn(&[u8]) -> bool,
+    ) -> io::Result<Option<Vec<u8>>> {
+        io::Write::write_all(self, query.as_bytes())?;
+        io::Write::flush(self)?;
+
+        let deadline = Instant::now() + timeout;
+        let mut reply = vec![];
+
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let mut fd = libc::pollfd {
+                fd: self.fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+
+            match c_result!(unsafe { libc::poll(&mut fd, 1, timeout.as_millis() as c_int) }) {
+                Ok(0) => return Ok(None),
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+
+            let mut buf = [0u8; 64];
+            let len =
+                c_result!(unsafe { libc::read(self.fd(), buf.as_mut_ptr().cast(), buf.len()) })?;
+            if len == 0 {
+                return Ok(None);
+            }
+
+            reply.extend_from_slice(&buf[..len as usize]);
+            if is_complete(&reply) {
+                return Ok(Some(reply));
+            }
+        }
     }
 }
 
 impl Drop for RawTerm {
     fn drop(&mut self) {
-        let _ = unsafe { set_termios(STDIN_FILENO, &self.termios_prev) };
+        let _ = unsafe { set_termios(self.fd(), &self.termios_prev) };
         RAW_TERM.store(false, Ordering::Relaxed);
     }
 }
 
 impl io::Write for RawTerm {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        unsafe { use_stdout(|stdout| stdout.write(buf)) }
+        self.tty.write(buf)
     }
 
     fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
-        unsafe { use_stdout(|stdout| stdout.write_vectored(bufs)) }
+        self.tty.write_vectored(bufs)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        unsafe { use_stdout(|stdout| stdout.flush()) }
+        self.tty.flush()
     }
 }
-
-/// Safety: don't close stdout by dropping the file.
-unsafe fn use_stdout<T>(f: impl Fn(&mut ManuallyDrop<File>) -> T) -> T {
-    let mut stdout = ManuallyDrop::new(File::from_raw_fd(STDOUT_FILENO));
-    f(&mut stdout)
-}
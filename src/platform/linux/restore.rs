@@ -0,0 +1,173 @@
+//! Restoring the terminal when the process dies without unwinding: on a panic
+//! (including with `panic=abort`), a terminating signal, or `process::exit`.
+
+use std::cell::UnsafeCell;
+use std::mem::{self, MaybeUninit};
+use std::os::fd::RawFd;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Mutex, Once};
+use std::thread::{self, ThreadId};
+use std::{io, panic, ptr};
+
+use libc::{c_int, sigaction as SigAction, termios as Termios};
+
+use super::signal::set_handler;
+
+/// Undoes everything `LinuxTerminal` sets up: resets the style, shows the
+/// cursor, and turns off paste and mouse reporting.
+const RESTORE: &[u8] = b"\x1b[0m\x1b[?25h\x1b[?2004l\x1b[?1006l\x1b[?1002l\x1b[?1000l";
+const LEAVE_ALT_SCREEN: &[u8] = b"\x1b[?1049l";
+
+const SIGNALS: [c_int; 3] = [libc::SIGTERM, libc::SIGINT, libc::SIGHUP];
+
+/// Whether there's a terminal to restore.
+static ARMED: AtomicBool = AtomicBool::new(false);
+static TTY: AtomicI32 = AtomicI32::new(-1);
+static ALT_SCREEN: AtomicBool = AtomicBool::new(false);
+/// The thread that set up the terminal, and which will restore it when
+/// unwinding.
+static OWNER: Mutex<Option<ThreadId>> = Mutex::new(None);
+/// Set when the panic hook restores the terminal, in case the panic is caught
+/// and the terminal gets used again.
+static PANIC_RESTORED: AtomicBool = AtomicBool::new(false);
+
+static TERMIOS: SavedTermios = SavedTermios(UnsafeCell::new(MaybeUninit::uninit()));
+
+/// Only written while `ARMED` is false, and only read after swapping it from
+/// true to false.
+struct SavedTermios(UnsafeCell<MaybeUninit<Termios>>);
+
+unsafe impl Sync for SavedTermios {}
+
+/// Restore the terminal, unless it's already been restored or disarmed,
+/// returning whether it was.
+///
+/// Only async-signal-safe functions are used, so this can be called from a
+/// signal handler.
+fn restore() -> bool {
+    if !ARMED.swap(false, Ordering::AcqRel) {
+        return false;
+    }
+
+    let tty = TTY.load(Ordering::Relaxed);
+
+    unsafe {
+        libc::write(tty, RESTORE.as_ptr().cast(), RESTORE.len());
+        if ALT_SCREEN.load(Ordering::Relaxed) {
+            libc::write(
+                tty,
+                LEAVE_ALT_SCREEN.as_ptr().cast(),
+                LEAVE_ALT_SCREEN.len(),
+            );
+        } else {
+            libc::write(tty, b"\r\n".as_ptr().cast(), 2);
+        }
+
+        libc::tcsetattr(tty, libc::TCSANOW, (*TERMIOS.0.get()).as_ptr());
+    }
+
+    true
+}
+
+/// Let the hooks restore the terminal again after disarming them.
+pub(super) fn arm() {
+    ARMED.store(true, Ordering::Release);
+}
+
+/// Stop the hooks from restoring the terminal, returning whether they would
+/// have. If they wouldn't, it's already been done.
+pub(super) fn disarm() -> bool {
+    ARMED.swap(false, Ordering::AcqRel)
+}
+
+/// Whether the panic hook has restored the terminal since the last call. If
+/// the terminal's still being used after that, the panic was caught.
+pub(super) fn take_panic_restored() -> bool {
+    PANIC_RESTORED.swap(false, Ordering::AcqRel)
+}
+
+extern "C" fn on_terminate(signal: c_int) {
+    restore();
+
+    // Die from the signal as we would have without the handler, so that the
+    // parent sees the right exit status. The signal is blocked until we return.
+    unsafe {
+        let mut action: SigAction = mem::zeroed();
+        action.sa_sigaction = libc::SIG_DFL;
+        libc::sigaction(signal, &action, ptr::null_mut());
+        libc::raise(signal);
+    }
+}
+
+extern "C" fn on_exit() {
+    restore();
+}
+
+/// Restores the terminal if the process panics, is killed by a signal or
+/// exits while it's alive.
+pub(super) struct RestoreGuard {
+    actions_prev: Vec<(c_int, SigAction)>,
+}
+
+impl RestoreGuard {
+    /// The terminal `tty` must stay open for as long as the guard is alive.
+    pub fn new(tty: RawFd, termios: &Termios, alt_screen: bool) -> io::Result<Self> {
+        unsafe { (*TERMIOS.0.get()).write(*termios) };
+        TTY.store(tty, Ordering::Relaxed);
+        ALT_SCREEN.store(alt_screen, Ordering::Relaxed);
+        PANIC_RESTORED.store(false, Ordering::Relaxed);
+        *OWNER.lock().unwrap_or_else(|err| err.into_inner()) = Some(thread::current().id());
+        ARMED.store(true, Ordering::Release);
+
+        // These can't be uninstalled, so they're installed once and do nothing
+        // while disarmed.
+        static HOOKS: Once = Once::new();
+        HOOKS.call_once(|| {
+            let hook_prev = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                // A panic on another thread only takes the process down with it
+                // if panics abort.
+                let owner = *OWNER.lock().unwrap_or_else(|err| err.into_inner());
+                if cfg!(panic = "abort") || owner == Some(thread::current().id()) {
+                    // Restore first, so that the message isn't lost on the
+                    // alternate screen or mangled by raw mode. If the panic is
+                    // caught, the terminal sets itself up again.
+                    if restore() {
+                        PANIC_RESTORED.store(true, Ordering::Release);
+                    }
+                }
+                hook_prev(info);
+            }));
+
+            unsafe { libc::atexit(on_exit) };
+        });
+
+        let mut guard = Self {
+            actions_prev: vec![],
+        };
+
+        for signal in SIGNALS {
+            let action_prev = unsafe { set_handler(signal, on_terminate)? };
+
+            // Leave ignored signals ignored, e.g. SIGHUP under `nohup`.
+            if action_prev.sa_sigaction == libc::SIG_IGN {
+                unsafe { libc::sigaction(signal, &action_prev, ptr::null_mut()) };
+                continue;
+            }
+
+            guard.actions_prev.push((signal, action_prev));
+        }
+
+        Ok(guard)
+    }
+}
+
+impl Drop for RestoreGuard {
+    fn drop(&mut self) {
+        disarm();
+
+        for (signal, action_prev) in &self.actions_prev {
+            unsafe { libc::sigaction(*signal, action_prev, ptr::null_mut()) };
+        }
+    }
+}
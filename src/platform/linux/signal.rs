@@ -0,0 +1,79 @@
+use std::io;
+use std::os::fd::AsRawFd;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::{mem, ptr};
+
+use libc::{c_int, sigaction as SigAction};
+
+use crate::platform::input::{Waker, RESIZE};
+
+/// The write end of the pipe that the `SIGWINCH` handler writes to, or -1.
+static RESIZE_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_sigwinch(_: c_int) {
+    let fd = RESIZE_PIPE.load(Ordering::Relaxed);
+    if fd < 0 {
+        return;
+    }
+
+    // Only async-signal-safe functions can be used here, so just poke the pipe and
+    // let the poller do the actual work. Make sure not to clobber `errno` for
+    // whatever code we interrupted.
+    unsafe {
+        let errno = *libc::__errno_location();
+        libc::write(fd, [RESIZE].as_ptr().cast(), 1);
+        *libc::__errno_location() = errno;
+    }
+}
+
+/// Install a signal handler, returning the previous action.
+pub(super) unsafe fn set_handler(
+    signal: c_int,
+    handler: extern "C" fn(c_int),
+) -> io::Result<SigAction> {
+    unsafe {
+        let mut action: SigAction = mem::zeroed();
+        action.sa_sigaction = handler as usize;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        let mut action_prev: SigAction = mem::zeroed();
+        c_result!(libc::sigaction(signal, &action, &mut action_prev))?;
+
+        Ok(action_prev)
+    }
+}
+
+/// Wakes up the input poller with a [`RESIZE`] byte on `SIGWINCH`.
+pub struct ResizeSignal {
+    action_prev: SigAction,
+
+    // Keeps the pipe open while the handler might write to it.
+    _waker: Waker,
+}
+
+impl ResizeSignal {
+    pub fn new(waker: Waker) -> io::Result<Self> {
+        RESIZE_PIPE.store(waker.as_raw_fd(), Ordering::Relaxed);
+
+        let action_prev = match unsafe { set_handler(libc::SIGWINCH, on_sigwinch) } {
+            Ok(action_prev) => action_prev,
+            Err(err) => {
+                RESIZE_PIPE.store(-1, Ordering::Relaxed);
+                return Err(err);
+            }
+        };
+
+        Ok(Self {
+            action_prev,
+            _waker: waker,
+        })
+    }
+}
+
+impl Drop for ResizeSignal {
+    fn drop(&mut self) {
+        unsafe { libc::sigaction(libc::SIGWINCH, &self.action_prev, ptr::null_mut()) };
+        RESIZE_PIPE.store(-1, Ordering::Relaxed);
+    }
+}
@@ -1,7 +1,20 @@
-mod ansi;
+macro_rules! c_result {
+    ($res:expr) => {{
+        match $res {
+            -1 => Err(io::Error::last_os_error()),
+            res => Ok(res),
+        }
+    }};
+}
+
+pub mod ansi;
 mod ansi_event;
+pub mod asciicast;
 mod input;
+#[cfg(feature = "async")]
+mod notifier;
 
+pub mod headless;
 pub mod linux;
 
 pub mod event;
@@ -13,6 +26,29 @@ pub use writer::Writer;
 
 use crate::vec2::Vec2;
 
+/// The part of the screen an app draws to.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Viewport {
+    /// The whole screen. The alternate screen is used, so the scrollback is left
+    /// as it was.
+    #[default]
+    Fullscreen,
+
+    /// This many lines starting at the cursor. The last frame is left in the
+    /// scrollback on exit.
+    Inline(u16),
+}
+
+impl Viewport {
+    /// The size of the viewport on a terminal of the given size.
+    pub fn size(self, term_size: Vec2) -> Vec2 {
+        match self {
+            Self::Fullscreen => term_size,
+            Self::Inline(height) => Vec2::new(term_size.x, height.min(term_size.y)),
+        }
+    }
+}
+
 pub trait Terminal: Sized {
     type Writer: Writer;
     type Events: event::Events;
@@ -22,6 +58,10 @@ pub trait Terminal: Sized {
 
     fn writer(&mut self) -> &mut Self::Writer;
     fn events(&mut self) -> &mut Self::Events;
+
+    /// Hand the terminal back to the shell and stop the process until it's
+    /// resumed. Everything has to be redrawn afterwards.
+    fn suspend(&mut self) -> io::Result<()>;
 }
 
 // #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
@@ -0,0 +1,166 @@
+//! Wakes up async tasks when the terminal has input, so that they don't have
+//! to block on `poll` themselves.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::task::Waker;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use libc::c_int;
+
+use super::input::pipe;
+
+/// A task waiting to be woken when one of the file descriptors is readable, or
+/// at the deadline.
+struct Request {
+    waker: Waker,
+    deadline: Option<Instant>,
+}
+
+#[derive(Default)]
+struct State {
+    request: Option<Request>,
+    polling: bool,
+    stopped: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    cond: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Polls file descriptors on a background thread.
+///
+/// The file descriptors must stay open for as long as the notifier is alive.
+pub(crate) struct Notifier {
+    shared: Arc<Shared>,
+
+    // Interrupts the thread's `poll`, when there's a new request or it's time to
+    // stop.
+    interrupt: File,
+
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Notifier {
+    pub fn new(fds: Vec<RawFd>) -> io::Result<Self> {
+        let (interrupt_read, interrupt) = pipe()?;
+        let shared = Arc::new(Shared::default());
+
+        let thread = {
+            let shared = shared.clone();
+            thread::spawn(move || run(&shared, fds, interrupt_read))
+        };
+
+        Ok(Self {
+            shared,
+            interrupt,
+            thread: Some(thread),
+        })
+    }
+
+    /// Wake the task once one of the file descriptors is readable, or at the
+    /// deadline. This replaces any earlier request.
+    pub fn notify(&mut self, waker: Waker, deadline: Option<Instant>) {
+        let mut state = self.shared.lock();
+        state.request = Some(Request { waker, deadline });
+
+        if state.polling {
+            let _ = self.interrupt.write(&[0]);
+        } else {
+            self.shared.cond.notify_one();
+        }
+    }
+}
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        self.shared.lock().stopped = true;
+        self.shared.cond.notify_one();
+        let _ = self.interrupt.write(&[0]);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(shared: &Shared, fds: Vec<RawFd>, mut interrupt: File) {
+    let mut pollfds: Vec<_> = fds
+        .into_iter()
+        .chain([interrupt.as_raw_fd()])
+        .map(|fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect();
+
+    let mut request = None;
+
+    loop {
+        // Wait for something to do, taking any newer request.
+        {
+            let mut state = shared.lock();
+            loop {
+                if state.stopped {
+                    return;
+                }
+                if let Some(new_request) = state.request.take() {
+                    request = Some(new_request);
+                }
+                if request.is_some() {
+                    break;
+                }
+                state = shared
+                    .cond
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+            state.polling = true;
+        }
+
+        let Request { waker, deadline } = request.as_ref().unwrap();
+
+        let timeout = match deadline {
+            Some(deadline) => {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                timeout.as_micros().div_ceil(1000).min(c_int::MAX as u128) as c_int
+            }
+            None => -1,
+        };
+
+        for pollfd in &mut pollfds {
+            pollfd.revents = 0;
+        }
+        let res = unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as _, timeout) };
+        let failed = res < 0 && io::Error::last_os_error().kind() != io::ErrorKind::Interrupted;
+
+        shared.lock().polling = false;
+
+        let (interrupt_fd, fds) = pollfds.split_last().unwrap();
+        if interrupt_fd.revents != 0 {
+            let mut buf = [0; 32];
+            while matches!(interrupt.read(&mut buf), Ok(len) if len > 0) {}
+        }
+
+        // Only wake the task if it has something to do, or if polling failed so
+        // that it can find out why by reading. Otherwise this was an interruption,
+        // and we go round again with the latest request.
+        let ready = res == 0 || fds.iter().any(|fd| fd.revents != 0);
+        if ready || failed {
+            waker.wake_by_ref();
+            request = None;
+        }
+    }
+}
@@ -1,10 +1,13 @@
 use std::io;
+use std::ops::Range;
 
-use crate::style::{Color, Style, Weight};
+use crate::style::{Color, Style, Underline, Weight};
 use crate::vec2::Vec2;
 
 pub trait Writer {
     fn flush(&mut self) -> io::Result<()>;
+    /// How many bytes the next flush will write.
+    fn buffered_len(&self) -> usize;
 
     fn clear_all(&mut self);
 
@@ -14,11 +17,32 @@ pub trait Writer {
     fn set_cursor_pos(&mut self, pos: impl Into<Vec2>);
     fn set_cursor_vis(&mut self, vis: bool);
 
+    /// Blank from the cursor to the end of the line, without moving the cursor.
+    fn clear_line_right(&mut self);
+    /// Blank the given number of cells from the cursor, without moving it.
+    fn erase_chars(&mut self, n: u16);
+    /// Write `s` `n` times over.
+    fn repeat_str(&mut self, s: &str, n: u16);
+    /// Move the given rows up by `amount` lines, or down if it's negative,
+    /// blanking the rows left behind. Leaves the cursor at home.
+    fn scroll_rows(&mut self, rows: Range<u16>, amount: i16);
+
+    fn set_alt_screen(&mut self, enabled: bool);
+    fn set_mouse_capture(&mut self, capture: bool);
+    fn set_bracketed_paste(&mut self, enabled: bool);
+
     fn set_fg_color(&mut self, c: Color);
     fn set_bg_color(&mut self, c: Color);
 
     fn set_weight(&mut self, weight: Weight);
-    fn set_underline(&mut self, underline: bool);
+    fn set_italic(&mut self, italic: bool);
+    fn set_strikethrough(&mut self, strikethrough: bool);
+    fn set_reverse(&mut self, reverse: bool);
+    fn set_blink(&mut self, blink: bool);
+    fn set_hidden(&mut self, hidden: bool);
+
+    fn set_underline(&mut self, underline: Underline);
+    fn set_underline_color(&mut self, c: Color);
 
     fn write_char(&mut self, c: char);
     fn write_str(&mut self, s: &str);
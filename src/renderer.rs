@@ -0,0 +1,128 @@
+//! Drawing buffers to a terminal, only redrawing what's changed.
+
+use crate::buffer::{Buffer, BufferView};
+use crate::draw_buffer::{draw_diff, draw_no_diff};
+use crate::platform::Writer;
+use crate::vec2::Vec2;
+
+/// Keeps what's on the screen, so that each frame only draws the difference.
+///
+/// This is what [`App`](crate::app::App) uses to draw, and it can be used
+/// without it by apps with their own event loops:
+///
+/// ```
+/// use tui::platform::ansi::AnsiWriter;
+/// use tui::platform::Writer;
+/// use tui::prelude::*;
+/// use tui::renderer::Renderer;
+///
+/// let mut renderer = Renderer::new();
+/// let mut w = AnsiWriter::new(vec![]);
+///
+/// let label = Label::new("hello");
+/// View::<()>::render(&label, &mut renderer.start_frame([10, 1]));
+/// renderer.draw(&mut w);
+/// w.flush().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Renderer {
+    front: Buffer,
+    back: Buffer,
+    should_redraw_all: bool,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Renderer {
+    pub fn new() -> Self {
+        Self {
+            front: Buffer::default(),
+            back: Buffer::default(),
+            should_redraw_all: true,
+        }
+    }
+
+    /// Clear the buffer for the next frame, returning it to render into.
+    pub fn start_frame(&mut self, size: impl Into<Vec2>) -> BufferView<'_> {
+        self.back.resize_and_clear(size);
+        self.back.view(true)
+    }
+
+    /// Draw the changes since the last frame, returning how many cells were
+    /// drawn. This doesn't flush the writer.
+    pub fn draw(&mut self, w: &mut impl Writer) -> usize {
+        let back = self.back.view(false);
+
+        let cells_drawn = if self.should_redraw_all {
+            self.should_redraw_all = false;
+            draw_no_diff(&back, w)
+        } else {
+            // TODO: make immutable view type.
+            draw_diff(&self.front.view(false), &back, w)
+        };
+
+        self.front.clone_from(&self.back);
+
+        cells_drawn
+    }
+
+    /// Draw everything next frame, e.g. because the terminal has been resized
+    /// or something else has drawn to it.
+    pub fn redraw_all(&mut self) {
+        self.should_redraw_all = true;
+    }
+
+    /// What was drawn last frame.
+    pub fn front(&self) -> &Buffer {
+        &self.front
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Renderer;
+    use crate::platform::ansi::AnsiWriter;
+    use crate::platform::headless::TestWriter;
+    use crate::platform::Writer;
+    use crate::style::Style;
+
+    fn frame(renderer: &mut Renderer, s: &str) {
+        renderer
+            .start_frame([8, 1])
+            .put_str([0, 0], s, Style::default());
+    }
+
+    #[test]
+    fn renderer() {
+        let mut renderer = Renderer::new();
+        let mut w = TestWriter::new([8, 1]);
+
+        frame(&mut renderer, "hello");
+        assert_eq!(renderer.draw(&mut w), 5);
+        assert_eq!(w.line(0), "hello");
+
+        frame(&mut renderer, "help");
+        assert_eq!(renderer.draw(&mut w), 2);
+        assert_eq!(w.line(0), "help");
+        assert_eq!(
+            renderer.front().to_text_snapshot(),
+            "+--------+\n|help    |\n+--------+\n"
+        );
+
+        // Only the first frame and ones after `redraw_all` clear the screen.
+        let mut w = AnsiWriter::new(vec![]);
+        frame(&mut renderer, "help");
+        renderer.draw(&mut w);
+        renderer.redraw_all();
+        frame(&mut renderer, "help");
+        renderer.draw(&mut w);
+        w.flush().unwrap();
+
+        let output = String::from_utf8(w.inner().clone()).unwrap();
+        assert_eq!(output.matches("\x1b[2J").count(), 1);
+    }
+}
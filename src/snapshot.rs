@@ -0,0 +1,89 @@
+//! Snapshot testing for views.
+//!
+//! Snapshots are stored in a `snapshots` directory next to the file the test is
+//! in. Set the `TUI_UPDATE_SNAPSHOTS` environment variable to create or update
+//! them instead of comparing against them.
+
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+use crate::buffer::Buffer;
+use crate::component::View;
+use crate::vec2::Vec2;
+
+/// The environment variable that makes snapshot assertions update the stored
+/// snapshots.
+pub const UPDATE_VAR: &str = "TUI_UPDATE_SNAPSHOTS";
+
+/// Render a view into a buffer of the given size.
+pub fn render_view<Message, V: View<Message> + ?Sized>(view: &V, size: impl Into<Vec2>) -> Buffer {
+    let mut buf = Buffer::new(size);
+    view.render(&mut buf.view(true));
+    buf
+}
+
+/// Work out where a snapshot is stored, given the manifest directory and the
+/// file the test is in.
+pub fn snapshot_path(manifest_dir: &str, file: &str, name: &str) -> PathBuf {
+    let dir = Path::new(manifest_dir)
+        .join(file)
+        .parent()
+        .map(|dir| dir.join("snapshots"))
+        .unwrap_or_else(|| PathBuf::from("snapshots"));
+
+    dir.join(format!("{name}.snap"))
+}
+
+/// Compare some text against a stored snapshot, or update the snapshot if
+/// [`UPDATE_VAR`] is set.
+#[track_caller]
+pub fn assert_snapshot(path: &Path, actual: &str) {
+    if should_update() {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).expect("failed to create snapshot directory");
+        }
+        fs::write(path, actual).expect("failed to write snapshot");
+        return;
+    }
+
+    let Ok(expected) = fs::read_to_string(path) else {
+        panic!(
+            "snapshot {} doesn't exist, run with {UPDATE_VAR}=1 to create it\n\nactual:\n{actual}",
+            path.display()
+        );
+    };
+
+    if expected != actual {
+        panic!(
+            "snapshot {} doesn't match, run with {UPDATE_VAR}=1 to update it\n\n\
+             expected:\n{expected}\nactual:\n{actual}",
+            path.display()
+        );
+    }
+}
+
+fn should_update() -> bool {
+    env::var_os(UPDATE_VAR).is_some_and(|value| !value.is_empty() && value != "0")
+}
+
+/// Render a view at the given size and compare it against a stored snapshot.
+///
+/// The message type of the view defaults to `()`, and can be given as a fourth
+/// argument for views that need a specific one.
+///
+/// ```text
+/// assert_view_snapshot!("label", Label::new("Hello"), [10, 1]);
+/// assert_view_snapshot!("field", TextField::new(), [10, 1], Message);
+/// ```
+#[macro_export]
+macro_rules! assert_view_snapshot {
+    ($name:expr, $view:expr, $size:expr $(,)?) => {
+        $crate::assert_view_snapshot!($name, $view, $size, ())
+    };
+
+    ($name:expr, $view:expr, $size:expr, $message:ty $(,)?) => {{
+        let buf = $crate::snapshot::render_view::<$message, _>(&$view, $size);
+        let path = $crate::snapshot::snapshot_path(env!("CARGO_MANIFEST_DIR"), file!(), $name);
+        $crate::snapshot::assert_snapshot(&path, &buf.to_text_snapshot());
+    }};
+}
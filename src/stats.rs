@@ -0,0 +1,186 @@
+//! Timings and counts for each frame, for finding out why frames are slow.
+
+use std::time::Duration;
+
+use crate::buffer::BufferView;
+use crate::component::View;
+use crate::style::Style;
+
+/// What went into drawing a frame.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Time spent in [`Component::update`](crate::component::Component::update).
+    pub update: Duration,
+    /// Time spent handling events, not counting waiting for them.
+    pub events: Duration,
+    /// Time spent handling messages and firing timers.
+    pub messages: Duration,
+    /// Time spent rendering the view tree.
+    pub render: Duration,
+    /// Time spent working out what changed on the screen.
+    pub draw_diff: Duration,
+    /// Time spent writing to the terminal.
+    pub flush: Duration,
+
+    /// Bytes written to the terminal, escape sequences included.
+    pub bytes_written: usize,
+    /// Cells drawn to the terminal because they changed, or all of them on a
+    /// full redraw.
+    pub cells_changed: usize,
+}
+
+impl FrameStats {
+    pub fn total(&self) -> Duration {
+        self.update + self.events + self.messages + self.render + self.draw_diff + self.flush
+    }
+}
+
+/// Shows [`FrameStats`] in the top right corner.
+pub struct StatsOverlay {
+    stats: FrameStats,
+}
+
+impl StatsOverlay {
+    const WIDTH: u16 = 20;
+
+    pub fn new(stats: FrameStats) -> Self {
+        Self { stats }
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let stats = &self.stats;
+        let time = |name: &str, time: Duration| {
+            format!(" {name:<9}{:>7.2}ms ", time.as_secs_f64() * 1000.0)
+        };
+        let count = |name: &str, count: usize| format!(" {name:<9}{count:>9} ");
+
+        vec![
+            time("update", stats.update),
+            time("events", stats.events),
+            time("messages", stats.messages),
+            time("render", stats.render),
+            time("draw", stats.draw_diff),
+            time("flush", stats.flush),
+            time("total", stats.total()),
+            count("bytes", stats.bytes_written),
+            count("cells", stats.cells_changed),
+        ]
+    }
+}
+
+impl<Message> View<Message> for StatsOverlay {
+    fn render(&self, buf: &mut BufferView) {
+        let size = buf.size();
+        let lines = self.lines();
+
+        let width = Self::WIDTH.min(size.x);
+        let mut buf = buf.view([size.x - width, 0], [size.x, lines.len() as u16], false);
+
+        let style = Style::new().with_reverse(true);
+        for (y, line) in lines.iter().enumerate() {
+            buf.put_str([0, y as u16], &format!("{line:20}"), style);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::platform::ansi::AnsiWriter;
+    use crate::platform::headless::{TestEvents, TestTerminal};
+    use crate::platform::Terminal;
+    use crate::prelude::*;
+
+    /// A text field to type into, under a label.
+    struct Field;
+
+    impl Component for Field {
+        type Message = ();
+        type View = Stack<()>;
+
+        fn build(&self) -> Self::View {
+            let mut stack = Stack::new();
+            stack.push(Label::new("Type something:"), SizeConstraint::fixed(1));
+            stack.push(TextField::new(), SizeConstraint::fixed(1));
+            stack.set_focus(Some(1));
+            stack
+        }
+    }
+
+    #[test]
+    fn stats() {
+        let f12 = KeyEvent::key(KeyCode::Fn(12));
+        let mut app = App::new_with_terminal(Field, TestTerminal::new([20, 4]))
+            .unwrap()
+            .with_stats_key(Some(f12));
+
+        assert!(app.run_frame().unwrap());
+        assert!(app.stats().cells_changed > 0);
+
+        app.terminal_mut().events().push_str("a");
+        assert!(app.run_frame().unwrap());
+        assert_eq!(app.stats().cells_changed, 1);
+
+        app.terminal_mut().events().push_key(f12);
+        assert!(app.run_frame().unwrap());
+        assert!(app.terminal_mut().writer().line(0).starts_with(" update"));
+        assert!(app.terminal_mut().writer().line(1).starts_with(" events"));
+
+        app.terminal_mut().events().push_key(f12);
+        assert!(app.run_frame().unwrap());
+        assert_eq!(app.terminal_mut().writer().line(0), "Type something:");
+        assert_eq!(app.terminal_mut().writer().line(1), "a");
+    }
+
+    /// Like a `TestTerminal`, but encodes what's drawn, so that it can be
+    /// counted.
+    struct AnsiTerminal {
+        writer: AnsiWriter<Vec<u8>>,
+        events: TestEvents,
+    }
+
+    impl Terminal for AnsiTerminal {
+        type Writer = AnsiWriter<Vec<u8>>;
+        type Events = TestEvents;
+
+        fn init() -> io::Result<Self> {
+            Ok(Self {
+                writer: AnsiWriter::new(vec![]),
+                events: TestEvents::default(),
+            })
+        }
+
+        fn size(&self) -> io::Result<Vec2> {
+            Ok(Vec2::new(20, 4))
+        }
+
+        fn writer(&mut self) -> &mut Self::Writer {
+            &mut self.writer
+        }
+
+        fn events(&mut self) -> &mut Self::Events {
+            &mut self.events
+        }
+
+        fn suspend(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn bytes_written() {
+        let mut app = App::new_with_terminal(Field, AnsiTerminal::init().unwrap()).unwrap();
+
+        assert!(app.run_frame().unwrap());
+        let first = app.stats().bytes_written;
+        assert_eq!(first, app.terminal().writer.inner().len());
+
+        // Typing a character only draws that character.
+        app.terminal_mut().events().push_str("a");
+        assert!(app.run_frame().unwrap());
+        let typed = app.stats().bytes_written;
+        assert!(0 < typed && typed < first);
+        assert_eq!(app.terminal().writer.inner().len(), first + typed);
+    }
+}
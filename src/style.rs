@@ -1,19 +1,204 @@
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+use std::env;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Color {
-    Black = 0,
-    Red = 1,
-    Green = 2,
-    Yellow = 3,
-    Blue = 4,
-    Magenta = 5,
-    Cyan = 6,
-    White = 7,
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+
+    /// A color from the 256-color palette.
+    Indexed(u8),
+
+    /// A 24-bit color.
+    Rgb(u8, u8, u8),
 
     #[default]
-    Default = 9,
+    Default,
+}
+
+const NAMED: [Color; 16] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::White,
+    Color::BrightBlack,
+    Color::BrightRed,
+    Color::BrightGreen,
+    Color::BrightYellow,
+    Color::BrightBlue,
+    Color::BrightMagenta,
+    Color::BrightCyan,
+    Color::BrightWhite,
+];
+
+/// The xterm defaults for the 16 named colors. Terminals let users change
+/// these, so they're only a best guess.
+const NAMED_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The levels of each channel in the 6x6x6 color cube of the 256-color
+/// palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl Color {
+    /// The index of a named color in the 256-color palette (0 to 15), if it is
+    /// one.
+    pub fn named_index(self) -> Option<u8> {
+        NAMED
+            .iter()
+            .position(|&named| named == self)
+            .map(|idx| idx as u8)
+    }
+
+    /// An approximation of the color as RGB, or `None` for the default color.
+    pub fn to_rgb(self) -> Option<(u8, u8, u8)> {
+        let idx = match self {
+            Self::Default => return None,
+            Self::Rgb(r, g, b) => return Some((r, g, b)),
+            Self::Indexed(idx) => idx,
+            named => named.named_index()?,
+        };
+
+        let rgb = match idx {
+            0..=15 => NAMED_RGB[idx as usize],
+            16..=231 => {
+                let idx = idx - 16;
+                (
+                    CUBE_LEVELS[(idx / 36) as usize],
+                    CUBE_LEVELS[(idx / 6 % 6) as usize],
+                    CUBE_LEVELS[(idx % 6) as usize],
+                )
+            }
+            232..=255 => {
+                let level = 8 + (idx - 232) * 10;
+                (level, level, level)
+            }
+        };
+
+        Some(rgb)
+    }
+
+    /// Convert the color to the closest one the terminal can display.
+    pub fn downsample(self, support: ColorSupport) -> Self {
+        match (self, support) {
+            (Self::Indexed(idx), _) if idx < 16 => NAMED[idx as usize],
+
+            (Self::Rgb(r, g, b), ColorSupport::Ansi256) => Self::Indexed(rgb_to_ansi256(r, g, b)),
+
+            (Self::Indexed(_) | Self::Rgb(..), ColorSupport::Ansi16) => {
+                let rgb = self.to_rgb().unwrap_or_default();
+                let idx = (0..16)
+                    .min_by_key(|&idx| distance(rgb, NAMED_RGB[idx]))
+                    .unwrap_or_default();
+                NAMED[idx]
+            }
+
+            (color, _) => color,
+        }
+    }
 }
 
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    fn nearest_level(c: u8) -> usize {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&idx| CUBE_LEVELS[idx].abs_diff(c))
+            .unwrap_or_default()
+    }
+
+    let (ri, gi, bi) = (nearest_level(r), nearest_level(g), nearest_level(b));
+    let cube_idx = 16 + 36 * ri + 6 * gi + bi;
+    let cube_rgb = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+
+    // The grayscale ramp is finer than the cube's grays, so check it too.
+    let avg = (r as u16 + g as u16 + b as u16) / 3;
+    let gray_idx = (avg.saturating_sub(3) / 10).min(23) as u8;
+    let gray_level = 8 + gray_idx * 10;
+
+    if distance((r, g, b), (gray_level, gray_level, gray_level)) < distance((r, g, b), cube_rgb) {
+        232 + gray_idx
+    } else {
+        cube_idx as u8
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let dr = a.0.abs_diff(b.0) as u32;
+    let dg = a.1.abs_diff(b.1) as u32;
+    let db = a.2.abs_diff(b.2) as u32;
+    dr * dr + dg * dg + db * db
+}
+
+/// Which colors a terminal can display.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ColorSupport {
+    /// The 8 basic colors and their bright variants.
+    #[default]
+    Ansi16,
+
+    /// The 256-color palette.
+    Ansi256,
+
+    /// 24-bit color.
+    TrueColor,
+}
+
+impl ColorSupport {
+    /// Guess the color support of the terminal from the `COLORTERM` and `TERM`
+    /// environment variables.
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").ok();
+        let term = env::var("TERM").ok();
+        Self::from_env(colorterm.as_deref(), term.as_deref())
+    }
+
+    fn from_env(colorterm: Option<&str>, term: Option<&str>) -> Self {
+        if matches!(colorterm, Some("truecolor" | "24bit")) {
+            return Self::TrueColor;
+        }
+
+        match term {
+            Some(term) if term.ends_with("-direct") => Self::TrueColor,
+            Some(term) if term.contains("256color") => Self::Ansi256,
+            _ => Self::Ansi16,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Weight {
     #[default]
     Normal,
@@ -21,13 +206,31 @@ pub enum Weight {
     Dim,
 }
 
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Underline {
+    #[default]
+    None,
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Style {
     pub fg: Color,
     pub bg: Color,
 
     pub weight: Weight,
-    pub underline: bool,
+    pub italic: bool,
+    pub strikethrough: bool,
+    pub reverse: bool,
+    pub blink: bool,
+    pub hidden: bool,
+
+    pub underline: Underline,
+    pub underline_color: Color,
 }
 
 impl Style {
@@ -37,7 +240,14 @@ impl Style {
             bg: Color::Default,
 
             weight: Weight::Normal,
-            underline: false,
+            italic: false,
+            strikethrough: false,
+            reverse: false,
+            blink: false,
+            hidden: false,
+
+            underline: Underline::None,
+            underline_color: Color::Default,
         }
     }
 
@@ -56,10 +266,40 @@ impl Style {
         self
     }
 
-    pub const fn with_underline(mut self, underline: bool) -> Self {
+    pub const fn with_italic(mut self, italic: bool) -> Self {
+        self.italic = italic;
+        self
+    }
+
+    pub const fn with_strikethrough(mut self, strikethrough: bool) -> Self {
+        self.strikethrough = strikethrough;
+        self
+    }
+
+    pub const fn with_reverse(mut self, reverse: bool) -> Self {
+        self.reverse = reverse;
+        self
+    }
+
+    pub const fn with_blink(mut self, blink: bool) -> Self {
+        self.blink = blink;
+        self
+    }
+
+    pub const fn with_hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    pub const fn with_underline(mut self, underline: Underline) -> Self {
         self.underline = underline;
         self
     }
+
+    pub const fn with_underline_color(mut self, underline_color: Color) -> Self {
+        self.underline_color = underline_color;
+        self
+    }
 }
 
 #[derive(Default, Debug, Clone, Copy)]
@@ -67,3 +307,58 @@ pub enum LineStyle {
     #[default]
     Line,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Color, ColorSupport};
+
+    #[test]
+    fn detect() {
+        use ColorSupport::*;
+
+        assert_eq!(ColorSupport::from_env(Some("truecolor"), None), TrueColor);
+        assert_eq!(
+            ColorSupport::from_env(Some("24bit"), Some("xterm")),
+            TrueColor
+        );
+        assert_eq!(
+            ColorSupport::from_env(None, Some("xterm-direct")),
+            TrueColor
+        );
+        assert_eq!(
+            ColorSupport::from_env(None, Some("xterm-256color")),
+            Ansi256
+        );
+        assert_eq!(ColorSupport::from_env(Some(""), Some("screen")), Ansi16);
+        assert_eq!(ColorSupport::from_env(None, None), Ansi16);
+    }
+
+    #[test]
+    fn downsample() {
+        use ColorSupport::*;
+
+        let orange = Color::Rgb(255, 135, 0);
+        assert_eq!(orange.downsample(TrueColor), orange);
+        assert_eq!(orange.downsample(Ansi256), Color::Indexed(208));
+        assert_eq!(orange.downsample(Ansi16), Color::Yellow);
+
+        assert_eq!(
+            Color::Rgb(128, 128, 128).downsample(Ansi256),
+            Color::Indexed(244)
+        );
+        assert_eq!(Color::Indexed(196).downsample(Ansi16), Color::BrightRed);
+        assert_eq!(Color::Indexed(9).downsample(TrueColor), Color::BrightRed);
+
+        assert_eq!(Color::Cyan.downsample(Ansi16), Color::Cyan);
+        assert_eq!(Color::Default.downsample(Ansi16), Color::Default);
+    }
+
+    #[test]
+    fn to_rgb() {
+        assert_eq!(Color::Indexed(16).to_rgb(), Some((0, 0, 0)));
+        assert_eq!(Color::Indexed(231).to_rgb(), Some((255, 255, 255)));
+        assert_eq!(Color::Indexed(232).to_rgb(), Some((8, 8, 8)));
+        assert_eq!(Color::BrightWhite.to_rgb(), Some((255, 255, 255)));
+        assert_eq!(Color::Default.to_rgb(), None);
+    }
+}
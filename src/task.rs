@@ -0,0 +1,277 @@
+//! Running work in the background, with the results delivered to the app as
+//! messages.
+
+use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+
+use crate::app::AppHandle;
+use crate::platform::event::EventWaker;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A pool of worker threads, which are started when the first task is.
+#[derive(Default)]
+pub(crate) struct TaskPool {
+    send: Option<Sender<Job>>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl TaskPool {
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+
+    /// Run `work` on a worker thread and pass its result to `done`. The waker is
+    /// woken once the task has finished, whether it completed or not.
+    fn run<T>(
+        &mut self,
+        work: impl FnOnce() -> T + Send + 'static,
+        done: impl FnOnce(T) + Send + 'static,
+        waker: EventWaker,
+    ) -> TaskHandle {
+        let handle = TaskHandle::default();
+        let cancelled = handle.cancelled.clone();
+
+        let in_flight = self.in_flight.clone();
+        in_flight.fetch_add(1, Ordering::AcqRel);
+
+        let job = Box::new(move || {
+            // Count the task as finished even if it panics.
+            let _finished = FinishGuard { in_flight, waker };
+
+            if cancelled.load(Ordering::Acquire) {
+                return;
+            }
+            let result = work();
+            if cancelled.load(Ordering::Acquire) {
+                return;
+            }
+
+            // The task only counts as finished once its message has been queued, so
+            // that a spinner can't stop before the result arrives.
+            done(result);
+        });
+
+        // The workers only stop once the pool is dropped, so this can't fail.
+        let _ = self.sender().send(job);
+
+        handle
+    }
+
+    fn sender(&mut self) -> &Sender<Job> {
+        self.send.get_or_insert_with(|| {
+            let (send, recv) = mpsc::channel();
+            let recv = Arc::new(Mutex::new(recv));
+
+            let workers = thread::available_parallelism().map_or(4, NonZeroUsize::get);
+            for _ in 0..workers {
+                let recv = recv.clone();
+                thread::spawn(move || work(&recv));
+            }
+
+            send
+        })
+    }
+}
+
+fn work(recv: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = recv.lock().unwrap_or_else(PoisonError::into_inner).recv();
+        match job {
+            // A panicking task shouldn't take the worker down with it.
+            Ok(job) => drop(panic::catch_unwind(AssertUnwindSafe(job))),
+            Err(_) => break,
+        }
+    }
+}
+
+struct FinishGuard {
+    in_flight: Arc<AtomicUsize>,
+    waker: EventWaker,
+}
+
+impl Drop for FinishGuard {
+    fn drop(&mut self) {
+        // Wake the app after counting the task as finished, so that it sees the
+        // new count along with the task's message.
+        self.in_flight.fetch_sub(1, Ordering::AcqRel);
+        self.waker.wake();
+    }
+}
+
+/// A task that's ready to run, created by
+/// [`Context::spawn`](crate::app::Context::spawn).
+#[must_use = "the task doesn't run until `then` or `detach` is called"]
+pub struct Spawn<'a, Message, F> {
+    pub(crate) pool: &'a mut TaskPool,
+    pub(crate) handle: &'a AppHandle<Message>,
+    pub(crate) work: F,
+}
+
+impl<Message, F, T> Spawn<'_, Message, F>
+where
+    Message: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+    T: 'static,
+{
+    /// Run the task, turning its result into a message for the app.
+    pub fn then(self, f: impl FnOnce(T) -> Message + Send + 'static) -> TaskHandle {
+        let handle = self.handle.clone();
+        let waker = handle.waker().clone();
+        self.pool.run(
+            self.work,
+            move |result| {
+                let _ = handle.queue(f(result));
+            },
+            waker,
+        )
+    }
+
+    /// Run the task, ignoring its result.
+    pub fn detach(self) -> TaskHandle {
+        let waker = self.handle.waker().clone();
+        self.pool.run(self.work, |_| {}, waker)
+    }
+}
+
+/// Cancels a running task.
+#[derive(Default, Debug, Clone)]
+pub struct TaskHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl TaskHandle {
+    /// Cancel the task. If it hasn't started it never will, and if it has its
+    /// result is thrown away.
+    ///
+    /// A task that's already running isn't interrupted.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::TaskPool;
+    use crate::platform::headless::TestTerminal;
+    use crate::prelude::*;
+
+    enum Message {
+        Start(u32),
+        Done(u32),
+    }
+
+    struct Squarer {
+        results: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl Component for Squarer {
+        type Message = Message;
+        type View = Label;
+
+        fn on_message(&mut self, ctx: &mut Context<Message>, msg: &Message) -> Handled {
+            match *msg {
+                Message::Start(n) => {
+                    ctx.spawn(move || n * n).then(Message::Done);
+                    assert_eq!(ctx.tasks_in_flight(), 1);
+                }
+                Message::Done(n) => self.results.lock().unwrap().push(n),
+            }
+            Handled::Yes
+        }
+
+        fn build(&self) -> Self::View {
+            Label::new("")
+        }
+    }
+
+    fn wait_until(mut f: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !f() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn spawn_then() {
+        let results = Arc::new(Mutex::new(vec![]));
+        let root = Squarer {
+            results: results.clone(),
+        };
+        let mut app = App::new_with_terminal(root, TestTerminal::new([10, 1])).unwrap();
+
+        app.handle()
+            .send(Message::Start(7))
+            .unwrap_or_else(|_| panic!());
+        wait_until(|| {
+            assert!(app.run_frame().unwrap());
+            !results.lock().unwrap().is_empty()
+        });
+
+        assert_eq!(*results.lock().unwrap(), [49]);
+    }
+
+    #[test]
+    fn finish_after_done() {
+        let mut pool = TaskPool::default();
+        let result = Arc::new(Mutex::new(None));
+
+        pool.run(
+            || 7 * 7,
+            {
+                let result = result.clone();
+                move |n| *result.lock().unwrap() = Some(n)
+            },
+            EventWaker::noop(),
+        );
+
+        // The result is delivered before the task stops counting as in flight.
+        wait_until(|| pool.in_flight() == 0);
+        assert_eq!(*result.lock().unwrap(), Some(49));
+    }
+
+    #[test]
+    fn cancel() {
+        let mut pool = TaskPool::default();
+        let (release, wait) = mpsc::channel::<()>();
+        let done = Arc::new(AtomicBool::new(false));
+        let woken = Arc::new(AtomicBool::new(false));
+
+        let task = pool.run(
+            move || {
+                let _ = wait.recv();
+            },
+            {
+                let done = done.clone();
+                move |()| done.store(true, Ordering::Relaxed)
+            },
+            {
+                let woken = woken.clone();
+                EventWaker::new(move || woken.store(true, Ordering::Relaxed))
+            },
+        );
+        assert_eq!(pool.in_flight(), 1);
+
+        task.cancel();
+        release.send(()).unwrap();
+        wait_until(|| pool.in_flight() == 0);
+        assert!(!done.load(Ordering::Relaxed));
+
+        // The app still hears that a cancelled task has finished.
+        wait_until(|| woken.load(Ordering::Relaxed));
+    }
+}
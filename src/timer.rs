@@ -0,0 +1,171 @@
+//! Sending messages after a delay, or repeatedly.
+
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+enum Kind<Message> {
+    Once(Message),
+    Every(Duration, Box<dyn Fn() -> Message>),
+}
+
+struct Timer<Message> {
+    at: Instant,
+    kind: Kind<Message>,
+    handle: TimerHandle,
+}
+
+/// The app's pending timers.
+pub(crate) struct Timers<Message> {
+    timers: Vec<Timer<Message>>,
+}
+
+impl<Message> Default for Timers<Message> {
+    fn default() -> Self {
+        Self { timers: vec![] }
+    }
+}
+
+impl<Message> Timers<Message> {
+    pub fn after(&mut self, now: Instant, delay: Duration, message: Message) -> TimerHandle {
+        self.add(now + delay, Kind::Once(message))
+    }
+
+    pub fn every(&mut self, now: Instant, interval: Duration, message: Message) -> TimerHandle
+    where
+        Message: Clone + 'static,
+    {
+        // It would never stop firing.
+        assert!(!interval.is_zero(), "timer interval must be non-zero");
+
+        self.add(
+            now + interval,
+            Kind::Every(interval, Box::new(move || message.clone())),
+        )
+    }
+
+    fn add(&mut self, at: Instant, kind: Kind<Message>) -> TimerHandle {
+        let handle = TimerHandle::default();
+        self.timers.push(Timer {
+            at,
+            kind,
+            handle: handle.clone(),
+        });
+        handle
+    }
+
+    /// When the next timer is due, if there is one.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.timers
+            .iter()
+            .filter(|timer| !timer.handle.is_cancelled())
+            .map(|timer| timer.at)
+            .min()
+    }
+
+    /// Send the messages of the timers that are due, in the order they were
+    /// due in.
+    pub fn fire(&mut self, now: Instant, messages: &mut Vec<Message>) {
+        let (mut due, pending): (Vec<_>, Vec<_>) = mem::take(&mut self.timers)
+            .into_iter()
+            .filter(|timer| !timer.handle.is_cancelled())
+            .partition(|timer| timer.at <= now);
+        self.timers = pending;
+
+        due.sort_by_key(|timer| timer.at);
+
+        for mut timer in due {
+            match timer.kind {
+                Kind::Once(message) => messages.push(message),
+                Kind::Every(interval, ref message) => {
+                    messages.push(message());
+
+                    // If we've fallen behind, skip the missed ticks rather than
+                    // sending them all at once.
+                    timer.at += interval;
+                    if timer.at <= now {
+                        timer.at = now + interval;
+                    }
+                    self.timers.push(timer);
+                }
+            }
+        }
+    }
+}
+
+/// Cancels a timer.
+#[derive(Default, Debug, Clone)]
+pub struct TimerHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl TimerHandle {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::Timers;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn after_and_every() {
+        let start = Instant::now();
+        let mut timers = Timers::default();
+        let mut messages = vec![];
+
+        timers.after(start, ms(30), "after");
+        timers.every(start, ms(20), "every");
+        assert_eq!(timers.next_deadline(), Some(start + ms(20)));
+
+        timers.fire(start + ms(10), &mut messages);
+        assert!(messages.is_empty());
+
+        timers.fire(start + ms(30), &mut messages);
+        assert_eq!(messages, ["every", "after"]);
+        assert_eq!(timers.next_deadline(), Some(start + ms(40)));
+
+        // Missed ticks are skipped.
+        messages.clear();
+        timers.fire(start + ms(100), &mut messages);
+        assert_eq!(messages, ["every"]);
+        assert_eq!(timers.next_deadline(), Some(start + ms(120)));
+    }
+
+    #[test]
+    fn cancel() {
+        let start = Instant::now();
+        let mut timers = Timers::default();
+        let mut messages = vec![];
+
+        let after = timers.after(start, ms(10), "after");
+        let every = timers.every(start, ms(10), "every");
+
+        after.cancel();
+        timers.fire(start + ms(10), &mut messages);
+        assert_eq!(messages, ["every"]);
+
+        every.cancel();
+        assert_eq!(timers.next_deadline(), None);
+        timers.fire(start + ms(20), &mut messages);
+        assert_eq!(messages, ["every"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "timer interval must be non-zero")]
+    fn zero_interval() {
+        Timers::default().every(Instant::now(), Duration::ZERO, "every");
+    }
+}
@@ -1,7 +1,7 @@
 use std::ops::{Add, Sub};
 
 /// A 16-bit 2D vector.
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Vec2 {
     pub x: u16,
     pub y: u16,
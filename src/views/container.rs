@@ -94,3 +94,34 @@ impl<Message> View<Message> for Container<Message> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::assert_view_snapshot;
+    use crate::prelude::*;
+
+    #[test]
+    fn borderless() {
+        assert_view_snapshot!(
+            "container_borderless",
+            Container::<()>::new(Label::new("Hello, World!")),
+            [8, 2]
+        );
+    }
+
+    #[test]
+    fn border() {
+        let container = Container::<()>::new(Label::new("Hello, World!"))
+            .with_border(LineStyle::Line, Style::default());
+
+        assert_view_snapshot!("container_border", container, [10, 4]);
+    }
+
+    #[test]
+    fn border_too_small() {
+        let container = Container::<()>::new(Label::new("Hello, World!"))
+            .with_border(LineStyle::Line, Style::default());
+
+        assert_view_snapshot!("container_border_too_small", container, [1, 4]);
+    }
+}
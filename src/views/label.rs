@@ -1,4 +1,3 @@
-use crate::buffer::Cell;
 use crate::prelude::*;
 
 #[derive(Default)]
@@ -29,8 +28,6 @@ impl<Message> View<Message> for Label {
             return;
         }
 
-        for (x, c) in self.s.chars().enumerate().take(size.x as usize) {
-            buf[[x as u16, 0]] = Some(Cell::new(c, self.style));
-        }
+        buf.put_str([0, 0], &self.s, self.style);
     }
 }
@@ -267,3 +267,35 @@ impl SizeConstraint {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::assert_view_snapshot;
+    use crate::prelude::*;
+
+    fn stack(direction: Direction) -> Stack<()> {
+        let mut stack = Stack::new().with_direction(direction);
+
+        stack.push(Label::new("fixed"), SizeConstraint::fixed(2));
+        stack.push(Label::new("min"), SizeConstraint::new().with_min(3));
+        stack.push(Label::new("max"), SizeConstraint::new().with_max(2));
+        stack.push(Label::new("any"), SizeConstraint::new());
+
+        stack
+    }
+
+    #[test]
+    fn down() {
+        assert_view_snapshot!("stack_down", stack(Direction::Down), [6, 12]);
+    }
+
+    #[test]
+    fn right() {
+        assert_view_snapshot!("stack_right", stack(Direction::Right), [16, 2]);
+    }
+
+    #[test]
+    fn too_small() {
+        assert_view_snapshot!("stack_too_small", stack(Direction::Down), [6, 4]);
+    }
+}
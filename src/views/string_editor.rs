@@ -1,3 +1,6 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::buffer::str_width;
 use crate::prelude::*;
 
 /// A string-based text buffer for small texts.
@@ -31,6 +34,11 @@ impl StringEditor {
         self.cursor_pos_chars
     }
 
+    /// The column the cursor is drawn at, taking wide characters into account.
+    pub fn cursor_col(&self) -> usize {
+        str_width(self.before_cursor())
+    }
+
     pub fn len(&self) -> usize {
         self.s.len()
     }
@@ -66,6 +74,18 @@ impl StringEditor {
     fn after_cursor(&self) -> &str {
         &self.s[self.cursor_pos..]
     }
+
+    /// The length in bytes and chars of the grapheme before the cursor.
+    fn prev_grapheme(&self) -> Option<(usize, usize)> {
+        let g = self.before_cursor().graphemes(true).next_back()?;
+        Some((g.len(), g.chars().count()))
+    }
+
+    /// The length in bytes and chars of the grapheme after the cursor.
+    fn next_grapheme(&self) -> Option<(usize, usize)> {
+        let g = self.after_cursor().graphemes(true).next()?;
+        Some((g.len(), g.chars().count()))
+    }
 }
 
 impl TextEdit for StringEditor {
@@ -88,35 +108,33 @@ impl TextEdit for StringEditor {
     }
 
     fn delete_char(&mut self) {
-        if self.cursor_pos == self.s.len() {
-            return;
+        if let Some((len, len_chars)) = self.next_grapheme() {
+            self.s.drain(self.cursor_pos..self.cursor_pos + len);
+            self.len_chars -= len_chars;
         }
-
-        self.s.remove(self.cursor_pos);
-        self.len_chars -= 1;
     }
 
     fn backspace(&mut self) {
-        if let Some(c) = self.before_cursor().chars().next_back() {
-            self.cursor_pos -= c.len_utf8();
-            self.cursor_pos_chars -= 1;
+        if let Some((len, len_chars)) = self.prev_grapheme() {
+            self.cursor_pos -= len;
+            self.cursor_pos_chars -= len_chars;
 
-            self.s.remove(self.cursor_pos);
-            self.len_chars -= 1;
+            self.s.drain(self.cursor_pos..self.cursor_pos + len);
+            self.len_chars -= len_chars;
         }
     }
 
     fn move_left(&mut self) {
-        if let Some(c) = self.before_cursor().chars().next_back() {
-            self.cursor_pos -= c.len_utf8();
-            self.cursor_pos_chars -= 1;
+        if let Some((len, len_chars)) = self.prev_grapheme() {
+            self.cursor_pos -= len;
+            self.cursor_pos_chars -= len_chars;
         }
     }
 
     fn move_right(&mut self) {
-        if let Some(c) = self.after_cursor().chars().next() {
-            self.cursor_pos += c.len_utf8();
-            self.cursor_pos_chars += 1;
+        if let Some((len, len_chars)) = self.next_grapheme() {
+            self.cursor_pos += len;
+            self.cursor_pos_chars += len_chars;
         }
     }
 
@@ -170,6 +188,10 @@ pub trait TextEdit {
 
                 _ => return Handled::No,
             },
+
+            // Pasted text goes in as-is, newlines and all.
+            Event::Paste(s) => self.insert_str(s),
+
             _ => return Handled::No,
         }
 
@@ -192,3 +214,49 @@ pub trait TextEdit {
 
     fn enter(&mut self);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{StringEditor, TextEdit};
+    use crate::prelude::*;
+
+    #[test]
+    fn graphemes() {
+        let mut editor = StringEditor::default();
+        editor.set_string("ae\u{301}日");
+        assert_eq!(editor.cursor_col(), 4);
+
+        editor.move_left();
+        assert_eq!(editor.cursor_col(), 2);
+
+        editor.backspace();
+        assert_eq!(editor.as_str(), "a日");
+        assert_eq!(editor.cursor_pos_chars(), 1);
+        assert_eq!(editor.len_chars(), 2);
+
+        editor.delete_char();
+        assert_eq!(editor.as_str(), "a");
+
+        editor.move_home();
+        editor.move_right();
+        assert_eq!(editor.cursor_pos(), 1);
+    }
+
+    #[test]
+    fn paste() {
+        let mut editor = StringEditor::default();
+        editor.set_string("ab");
+        editor.move_left();
+
+        let pasted = "1\r\n2\x1b[A\t3";
+        let handled = editor.handle_event(&Event::Paste(pasted.to_owned()));
+        assert_eq!(handled, Handled::Yes);
+
+        // Newlines and control characters go in as they are, without entering.
+        assert_eq!(editor.as_str(), "a1\r\n2\x1b[A\t3b");
+        assert_eq!(editor.entered(), None);
+
+        assert_eq!(editor.cursor_pos(), 1 + pasted.len());
+        assert_eq!(editor.cursor_pos_chars(), 1 + pasted.chars().count());
+    }
+}
@@ -1,5 +1,4 @@
 use super::string_editor::{StringEditor, TextEdit};
-use crate::buffer::Cell;
 use crate::prelude::*;
 
 pub struct TextField<Message> {
@@ -60,17 +59,9 @@ impl<Message> View<Message> for TextField<Message> {
             return;
         }
 
-        for (x, c) in self
-            .editor
-            .as_str()
-            .chars()
-            .enumerate()
-            .take(size.x as usize)
-        {
-            buf[[x as u16, 0]] = Some(Cell::new(c, self.style));
-        }
+        buf.put_str([0, 0], self.editor.as_str(), self.style);
 
-        let cursor_x = self.editor.cursor_pos_chars();
+        let cursor_x = self.editor.cursor_col();
         if cursor_x < size.x as usize {
             buf.set_cursor(Some([cursor_x as u16, 0]));
         }